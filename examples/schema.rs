@@ -3,7 +3,12 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use cw_timekeeper::msg::{HandleMsg, InfoResponse, InitMsg, QueryMsg};
+use cw_timekeeper::msg::{
+    AdminsResponse, BlockTimeResponse, CallbacksResponse, CheckpointsResponse, CurrentTimeResponse,
+    HandleMsg, HeaderResponse, InfoResponse, InitMsg, MigrateMsg, QueryMsg,
+    RegisterCallbackResponse, RelayerRewardsResponse, RewardPoolResponse, StatusResponse,
+    TxInclusionResponse, UpdateResult, VersionResponse,
+};
 use cw_timekeeper::state::State;
 
 fn main() {
@@ -15,6 +20,20 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
     export_schema(&schema_for!(InfoResponse), &out_dir);
+    export_schema(&schema_for!(CurrentTimeResponse), &out_dir);
+    export_schema(&schema_for!(HeaderResponse), &out_dir);
+    export_schema(&schema_for!(BlockTimeResponse), &out_dir);
+    export_schema(&schema_for!(StatusResponse), &out_dir);
+    export_schema(&schema_for!(TxInclusionResponse), &out_dir);
+    export_schema(&schema_for!(AdminsResponse), &out_dir);
+    export_schema(&schema_for!(RewardPoolResponse), &out_dir);
+    export_schema(&schema_for!(RelayerRewardsResponse), &out_dir);
+    export_schema(&schema_for!(RegisterCallbackResponse), &out_dir);
+    export_schema(&schema_for!(CallbacksResponse), &out_dir);
+    export_schema(&schema_for!(VersionResponse), &out_dir);
+    export_schema(&schema_for!(CheckpointsResponse), &out_dir);
+    export_schema(&schema_for!(UpdateResult), &out_dir);
 }
@@ -0,0 +1,113 @@
+use std::env;
+use std::process;
+
+use cw_timekeeper::relayer::{relay, FileSource, HeaderSource, RestSource};
+
+const USAGE: &str = "Usage: timekeeper-relayer --from-hash <hash> (--rest <url> | --file <path>)
+                          --min-update-length <n> [--batch-size <n>] [--count <n>]
+
+Prints update_block_offset messages for the block headers after the block with the
+given little endian hash (the curr_hash in get_contract_info), one JSON message per
+line, to pass to secretcli tx compute execute.
+
+  --from-hash <hash>         the block to start after
+  --rest <url>               a Bitcoin Core node with -rest enabled, like http://127.0.0.1:8332
+  --file <path>              a file with one 160 character hex block header per line
+  --min-update-length <n>    the contract's min_update_length
+  --batch-size <n>           headers per message, defaults to the min update length
+  --count <n>                the most headers to read, defaults to 100";
+
+struct Args {
+    from_hash: String,
+    rest: Option<String>,
+    file: Option<String>,
+    min_update_length: u32,
+    batch_size: Option<u32>,
+    count: u32,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut from_hash = None;
+    let mut rest = None;
+    let mut file = None;
+    let mut min_update_length = None;
+    let mut batch_size = None;
+    let mut count = 100;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value.clone(),
+            None => return Err(format!("Missing value for {}", arg)),
+        };
+        let number = || {
+            value
+                .parse::<u32>()
+                .map_err(|err| format!("Invalid value for {}: {}", arg, err))
+        };
+        match arg.as_str() {
+            "--from-hash" => from_hash = Some(value.to_lowercase()),
+            "--rest" => rest = Some(value.clone()),
+            "--file" => file = Some(value.clone()),
+            "--min-update-length" => min_update_length = Some(number()?),
+            "--batch-size" => batch_size = Some(number()?),
+            "--count" => count = number()?,
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    if rest.is_some() == file.is_some() {
+        return Err("Exactly one of --rest and --file is required".to_string());
+    }
+    Ok(Args {
+        from_hash: from_hash.ok_or_else(|| "--from-hash is required".to_string())?,
+        rest,
+        file,
+        min_update_length: min_update_length
+            .ok_or_else(|| "--min-update-length is required".to_string())?,
+        batch_size,
+        count,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut source: Box<dyn HeaderSource> = match (args.rest, args.file) {
+        (Some(url), _) => Box::new(RestSource { url }),
+        (_, Some(path)) => match FileSource::open(&path) {
+            Ok(source) => Box::new(source),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        },
+        _ => unreachable!(),
+    };
+
+    let batch_size = args.batch_size.unwrap_or(args.min_update_length);
+    match relay(
+        source.as_mut(),
+        &args.from_hash,
+        args.count,
+        args.min_update_length,
+        batch_size,
+    ) {
+        Ok(messages) => {
+            for msg in messages {
+                println!("{}", msg);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Binary, CanonicalAddr, HumanAddr, StdResult, Storage};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
+
+pub static CALLBACKS_KEY: &[u8] = b"callbacks";
+pub static PENDING_CALLBACKS_KEY: &[u8] = b"pending_callbacks";
+pub static NEXT_CALLBACK_ID_KEY: &[u8] = b"next_callback_id";
+
+// When a callback becomes due.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackCondition {
+    // The tip reaches this Bitcoin block height.
+    Height(u32),
+    // The median time past of the tip reaches this time. The median time past
+    // never decreases, unlike block timestamps.
+    Time(u32),
+}
+
+// A message to execute on another contract once its condition is met.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Callback {
+    pub id: u64,
+    // The address that registered the callback and can cancel it.
+    pub registrant: CanonicalAddr,
+    pub condition: CallbackCondition,
+    pub contract_addr: HumanAddr,
+    pub callback_code_hash: String,
+    pub msg: Binary,
+}
+
+// Callbacks that haven't been executed or cancelled yet, keyed by id.
+pub fn callbacks<S: Storage>(storage: &mut S) -> Bucket<S, Callback> {
+    bucket(CALLBACKS_KEY, storage)
+}
+
+pub fn callbacks_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Callback> {
+    bucket_read(CALLBACKS_KEY, storage)
+}
+
+// Ids of the pending callbacks in the order they were registered, since
+// buckets can't be iterated.
+pub fn pending_callbacks<S: Storage>(storage: &mut S) -> Singleton<S, Vec<u64>> {
+    singleton(storage, PENDING_CALLBACKS_KEY)
+}
+
+pub fn pending_callbacks_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<u64>> {
+    singleton_read(storage, PENDING_CALLBACKS_KEY)
+}
+
+pub fn next_callback_id<S: Storage>(storage: &mut S) -> Singleton<S, u64> {
+    singleton(storage, NEXT_CALLBACK_ID_KEY)
+}
+
+pub fn callback_key(id: u64) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+// Stores a new callback and returns its id.
+pub fn add_callback<S: Storage>(storage: &mut S, mut callback: Callback) -> StdResult<u64> {
+    let id = next_callback_id(storage).may_load()?.unwrap_or(0);
+    next_callback_id(storage).save(&(id + 1))?;
+    callback.id = id;
+    callbacks(storage).save(&callback_key(id), &callback)?;
+    pending_callbacks(storage).update(|mut pending| {
+        pending.push(id);
+        Ok(pending)
+    })?;
+    Ok(id)
+}
+
+// Removes a pending callback.
+pub fn remove_callback<S: Storage>(storage: &mut S, id: u64) -> StdResult<()> {
+    callbacks(storage).remove(&callback_key(id));
+    pending_callbacks(storage).update(|mut pending| {
+        pending.retain(|pending_id| *pending_id != id);
+        Ok(pending)
+    })?;
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{StdResult, Storage};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
+
+use crate::history::height_key;
+
+pub static CHECKPOINTS_KEY: &[u8] = b"checkpoints";
+pub static CHECKPOINT_HEIGHTS_KEY: &[u8] = b"checkpoint_heights";
+
+// A block hash that the header at the height must have.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Checkpoint {
+    pub height: u32,
+    // Little endian hex, like the hashes the contract stores.
+    pub hash: String,
+}
+
+// Checkpointed block hashes keyed by height.
+pub fn checkpoints<S: Storage>(storage: &mut S) -> Bucket<S, String> {
+    bucket(CHECKPOINTS_KEY, storage)
+}
+
+pub fn checkpoints_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, String> {
+    bucket_read(CHECKPOINTS_KEY, storage)
+}
+
+// Heights of the checkpoints in increasing order, since buckets can't be iterated.
+pub fn checkpoint_heights<S: Storage>(storage: &mut S) -> Singleton<S, Vec<u32>> {
+    singleton(storage, CHECKPOINT_HEIGHTS_KEY)
+}
+
+pub fn checkpoint_heights_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<u32>> {
+    singleton_read(storage, CHECKPOINT_HEIGHTS_KEY)
+}
+
+// The checkpointed hash at the height, if there is one.
+pub fn checkpoint_at<S: Storage>(storage: &S, height: u32) -> StdResult<Option<String>> {
+    checkpoints_read(storage).may_load(&height_key(height))
+}
+
+// Stores a checkpoint, replacing any at the same height.
+pub fn add_checkpoint<S: Storage>(storage: &mut S, checkpoint: &Checkpoint) -> StdResult<()> {
+    checkpoints(storage).save(&height_key(checkpoint.height), &checkpoint.hash)?;
+    let height = checkpoint.height;
+    checkpoint_heights(storage).update(|mut heights| {
+        if let Err(pos) = heights.binary_search(&height) {
+            heights.insert(pos, height);
+        }
+        Ok(heights)
+    })?;
+    Ok(())
+}
+
+// Removes the checkpoint at the height. Returns whether there was one.
+pub fn remove_checkpoint<S: Storage>(storage: &mut S, height: u32) -> StdResult<bool> {
+    if checkpoint_at(storage, height)?.is_none() {
+        return Ok(false);
+    }
+    checkpoints(storage).remove(&height_key(height));
+    checkpoint_heights(storage).update(|mut heights| {
+        heights.retain(|checkpoint_height| *checkpoint_height != height);
+        Ok(heights)
+    })?;
+    Ok(true)
+}
@@ -0,0 +1,256 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{to_binary, HumanAddr, Querier, QueryRequest, StdError, StdResult, WasmQuery};
+use schemars::JsonSchema;
+use snafu::{Backtrace, GenerateBacktrace};
+
+use crate::msg::{CurrentTimeResponse, InfoResponse, QueryMsg};
+
+// A timekeeper contract, for querying it from another contract. Depend on this crate
+// with the "library" feature so that its init, handle and query exports aren't
+// compiled into the other contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TimekeeperRef {
+    pub address: HumanAddr,
+    pub code_hash: String,
+}
+
+impl TimekeeperRef {
+    pub fn query<Q: Querier, T: DeserializeOwned>(
+        &self,
+        querier: &Q,
+        msg: &QueryMsg,
+    ) -> StdResult<T> {
+        querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: self.address.clone(),
+            callback_code_hash: self.code_hash.clone(),
+            msg: to_binary(msg)?,
+        }))
+    }
+
+    pub fn info<Q: Querier>(&self, querier: &Q) -> StdResult<InfoResponse> {
+        self.query(querier, &QueryMsg::GetContractInfo {})
+    }
+
+    pub fn current_time<Q: Querier>(&self, querier: &Q) -> StdResult<CurrentTimeResponse> {
+        self.query(
+            querier,
+            &QueryMsg::GetCurrentTime {
+                confirmations: None,
+            },
+        )
+    }
+
+    // Height of the latest block header accepted by the timekeeper.
+    pub fn tip_height<Q: Querier>(&self, querier: &Q) -> StdResult<u32> {
+        let info = self.info(querier)?;
+        Ok(info.start_height + info.curr_offset)
+    }
+
+    // Whether the block at the height has at least the given number of confirmations,
+    // counting the block itself like Bitcoin Core does. Returns an error while the
+    // timekeeper is paused, since its tip can't be relied on then.
+    pub fn has_height_passed<Q: Querier>(
+        &self,
+        querier: &Q,
+        height: u32,
+        confirmations: u32,
+    ) -> StdResult<bool> {
+        let info = self.info(querier)?;
+        if info.paused {
+            return Err(StdError::GenericErr {
+                msg: format!("Timekeeper {} is paused", self.address),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+        let tip_height = info.start_height + info.curr_offset;
+        Ok(tip_height >= height && tip_height - height + 1 >= confirmations)
+    }
+}
+
+// Helpers for unit testing contracts that query the timekeeper.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod testing {
+    use cosmwasm_std::testing::{mock_dependencies, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{
+        from_slice, to_binary, Coin, Empty, Extern, HumanAddr, Querier, QuerierResult,
+        QueryRequest, StdError, SystemError, WasmQuery,
+    };
+
+    use snafu::{Backtrace, GenerateBacktrace};
+
+    use crate::contract::MAX_FUTURE_BLOCK_TIME;
+    use crate::msg::{CurrentTimeResponse, InfoResponse, QueryMsg};
+    use crate::network::Network;
+
+    // A fake timekeeper that answers the contract info and current time queries.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct MockTimekeeper {
+        pub info: InfoResponse,
+        pub current_time: CurrentTimeResponse,
+    }
+
+    impl MockTimekeeper {
+        // A timekeeper started at the height, with the tip and median time past at the time.
+        pub fn new(start_height: u32, time: u32) -> Self {
+            let mut timekeeper = MockTimekeeper {
+                info: InfoResponse {
+                    start_height,
+                    min_difficulty: "0".repeat(64),
+                    curr_hash: "00".repeat(32),
+                    curr_offset: 0,
+                    min_update_length: 1,
+                    max_update_length: 0,
+                    finality_depth: 0,
+                    chainwork: "0".to_string(),
+                    paused: false,
+                    network: Network::Mainnet,
+                },
+                current_time: CurrentTimeResponse {
+                    tip_height: start_height,
+                    tip_time: 0,
+                    median_time_past: 0,
+                    lower_bound_time: 0,
+                    paused: false,
+                },
+            };
+            timekeeper.set_tip(start_height, time);
+            timekeeper
+        }
+
+        // Moves the tip, using the time as both the tip's timestamp and the median time past.
+        pub fn set_tip(&mut self, height: u32, time: u32) {
+            self.info.curr_offset = height - self.info.start_height;
+            self.current_time.tip_height = height;
+            self.current_time.tip_time = time;
+            self.current_time.median_time_past = time;
+            self.current_time.lower_bound_time = time.saturating_sub(MAX_FUTURE_BLOCK_TIME as u32);
+        }
+
+        pub fn set_paused(&mut self, paused: bool) {
+            self.info.paused = paused;
+            self.current_time.paused = paused;
+        }
+
+        fn query(&self, msg: &[u8]) -> QuerierResult {
+            let msg: QueryMsg = match from_slice(msg) {
+                Ok(msg) => msg,
+                Err(err) => return Ok(Err(err)),
+            };
+            match msg {
+                QueryMsg::GetContractInfo {} => Ok(to_binary(&self.info)),
+                QueryMsg::GetCurrentTime {
+                    confirmations: None,
+                } => Ok(to_binary(&self.current_time)),
+                _ => Ok(Err(StdError::GenericErr {
+                    msg: "The mock timekeeper only answers get_contract_info, and get_current_time without confirmations"
+                        .to_string(),
+                    backtrace: Option::Some(Backtrace::generate()),
+                })),
+            }
+        }
+    }
+
+    // Answers smart queries to the timekeeper's address with a MockTimekeeper, and
+    // passes every other query to the wrapped querier.
+    pub struct MockTimekeeperQuerier<Q: Querier> {
+        pub base: Q,
+        pub address: HumanAddr,
+        pub timekeeper: MockTimekeeper,
+    }
+
+    impl<Q: Querier> Querier for MockTimekeeperQuerier<Q> {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = match from_slice(bin_request) {
+                Ok(request) => request,
+                Err(err) => {
+                    return Err(SystemError::InvalidRequest {
+                        error: format!("Parsing query request: {}", err),
+                        request: bin_request.into(),
+                    });
+                }
+            };
+            match request {
+                QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr, msg, ..
+                }) if contract_addr == self.address => self.timekeeper.query(msg.as_slice()),
+                _ => self.base.raw_query(bin_request),
+            }
+        }
+    }
+
+    // Like mock_dependencies, with a fake timekeeper at the address.
+    pub fn mock_dependencies_with_timekeeper(
+        canonical_length: usize,
+        contract_balance: &[Coin],
+        address: HumanAddr,
+        timekeeper: MockTimekeeper,
+    ) -> Extern<MockStorage, MockApi, MockTimekeeperQuerier<MockQuerier>> {
+        let deps = mock_dependencies(canonical_length, contract_balance);
+        Extern {
+            storage: deps.storage,
+            api: deps.api,
+            querier: MockTimekeeperQuerier {
+                base: deps.querier,
+                address,
+                timekeeper,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{mock_dependencies_with_timekeeper, MockTimekeeper};
+    use super::*;
+    use cosmwasm_std::{coins, StdError};
+
+    #[test]
+    fn query_mock_timekeeper() {
+        let timekeeper = TimekeeperRef {
+            address: HumanAddr::from("timekeeper"),
+            code_hash: "abcd".to_string(),
+        };
+        let mut deps = mock_dependencies_with_timekeeper(
+            20,
+            &coins(2, "token"),
+            timekeeper.address.clone(),
+            MockTimekeeper::new(1000, 1500000000),
+        );
+
+        assert_eq!(1000, timekeeper.tip_height(&deps.querier).unwrap());
+        assert!(timekeeper
+            .has_height_passed(&deps.querier, 1000, 1)
+            .unwrap());
+        assert!(!timekeeper
+            .has_height_passed(&deps.querier, 1000, 2)
+            .unwrap());
+        assert!(!timekeeper
+            .has_height_passed(&deps.querier, 1001, 0)
+            .unwrap());
+
+        deps.querier.timekeeper.set_tip(1005, 1500003000);
+        assert!(timekeeper
+            .has_height_passed(&deps.querier, 1000, 6)
+            .unwrap());
+        let value = timekeeper.current_time(&deps.querier).unwrap();
+        assert_eq!(1005, value.tip_height);
+        assert_eq!(1500003000, value.median_time_past);
+
+        deps.querier.timekeeper.set_paused(true);
+        match timekeeper.has_height_passed(&deps.querier, 1000, 1) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Timekeeper timekeeper is paused")
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // other contracts are passed to the wrapped querier
+        let other = TimekeeperRef {
+            address: HumanAddr::from("other"),
+            code_hash: "abcd".to_string(),
+        };
+        assert!(other.info(&deps.querier).is_err());
+    }
+}
@@ -1,27 +1,79 @@
 use cosmwasm_std::{
-    to_binary, Api, Binary, Env, Extern, HandleResponse, InitResponse, Querier, StdError,
-    StdResult, Storage,
+    coins, log, to_binary, Api, BankMsg, Binary, CanonicalAddr, CosmosMsg, Env, Extern,
+    HandleResponse, HumanAddr, InitResponse, LogAttribute, MigrateResponse, Querier, StdError,
+    StdResult, Storage, Uint128, WasmMsg,
 };
 
 extern crate rustc_hex as hex;
-use crate::msg::{HandleMsg, InfoResponse, InitMsg, QueryMsg};
-use crate::state::{config, config_read, State};
+use crate::callbacks::{
+    add_callback, callback_key, callbacks_read, pending_callbacks, pending_callbacks_read,
+    remove_callback, Callback, CallbackCondition,
+};
+use crate::checkpoints::{
+    add_checkpoint, checkpoint_at, checkpoint_heights, checkpoint_heights_read, remove_checkpoint,
+    Checkpoint,
+};
+use crate::error::ContractError;
+use crate::header::{double_sha256, BlockHash, BlockHeader, BLOCK_HEADER_SIZE};
+use crate::history::{
+    find_oldest_header, header_heights_read, headers_read, height_key, prune_headers, store_header,
+    HeaderRecord,
+};
+use crate::msg::{
+    AdminInfo, AdminsResponse, BlockTimeResponse, CallbackInfo, CallbacksResponse,
+    CheckpointsResponse, CurrentTimeResponse, HandleMsg, HeaderResponse, InfoResponse, InitMsg,
+    MigrateFromV0, MigrateMsg, QueryMsg, RegisterCallbackResponse, RejectedHeader,
+    RelayerRewardsResponse, RewardPoolResponse, StatusResponse, TxInclusionResponse, UpdateResult,
+    VersionResponse,
+};
+use crate::network::{Network, NetworkParams};
+use crate::state::{
+    admins, admins_read, config, config_read, config_v0_read, relayer_rewards,
+    relayer_rewards_read, rewards, rewards_read, timestamps, timestamps_read, version,
+    version_read, Admin, Permission, Rewards, State,
+};
 
 use hex::{FromHex, ToHex};
 use primitive_types::U256;
-use sha2::{Digest, Sha256};
 use snafu::{Backtrace, GenerateBacktrace};
 use std::convert::TryFrom;
-use std::num::ParseIntError;
 
+// Version of the layout of the stored state. Bump it whenever the layout changes,
+// and add a step to migrate if the previous layout needs upgrading.
+pub const CONTRACT_VERSION: u32 = 8;
 // Represents the length of an 80 byte block header hex string.
-const BLOCK_HEADER_LEN: usize = 160;
+pub const BLOCK_HEADER_LEN: usize = 160;
+// Number of previous block timestamps used to compute the median time past.
+const MEDIAN_TIME_SPAN: usize = 11;
+// How far in seconds a block timestamp may be ahead of the current time.
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+// Relayer rewards are paid in this denomination.
+pub const REWARD_DENOM: &str = "uscrt";
+// Most callbacks executed by a single update or TriggerCallbacks, so that they don't
+// run out of gas.
+const MAX_CALLBACKS_PER_UPDATE: usize = 10;
+// Every update checks every pending callback, so the number that a single address
+// can have pending is limited.
+const MAX_CALLBACKS_PER_REGISTRANT: usize = 10;
+// Maximum number of header records that fall out of a lowered retention to remove
+// at once, on top of one for every header that an update adds.
+const MAX_PRUNED_HEADERS: usize = 100;
+// How far in seconds the tip can be behind the Secret Network block time before the
+// contract is reported as stale, unless configured otherwise.
+pub const DEFAULT_STALE_THRESHOLD: u64 = 3 * 60 * 60;
+// Default and maximum page sizes for listing callbacks and checkpoints.
+const DEFAULT_PAGE_LIMIT: u32 = 10;
+const MAX_PAGE_LIMIT: u32 = 30;
+// A block can't have more than 2^32 transactions, so no merkle branch is longer than this.
+const MAX_MERKLE_BRANCH_LEN: usize = 32;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
+    let recent_times = initial_timestamps(&msg);
+    let start_record = start_header_record(&msg)?;
     let state = State {
         curr_hash: msg.start_hash,
         curr_offset: 0,
@@ -29,11 +81,160 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
         threshold_difficulty: format!("{:x}", bits_to_difficulty(msg.min_difficulty_bits)),
         min_update_length: msg.min_update_length,
         owner: deps.api.canonical_address(&env.message.sender)?,
+        pending_owner: None,
+        curr_bits: msg.start_bits,
+        curr_time: msg.start_time,
+        epoch_start_time: msg.epoch_start_time,
+        header_retention: msg.header_retention,
+        chainwork: start_record.chainwork.clone(),
+        paused: false,
+        network: msg.network.unwrap_or_default(),
+        epoch_bits: msg.epoch_start_bits.unwrap_or(msg.start_bits),
+        max_update_length: msg.max_update_length.unwrap_or(0),
+        finality_depth: msg.finality_depth.unwrap_or(0),
+        last_update_block_height: env.block.height,
+        last_update_block_time: env.block.time,
+        stale_threshold: msg.stale_threshold.unwrap_or(DEFAULT_STALE_THRESHOLD),
+        oldest_height: msg.start_height,
     };
+    check_update_length_limits(&state)?;
+    check_finality_depth(&state)?;
 
+    version(&mut deps.storage).save(&CONTRACT_VERSION)?;
     config(&mut deps.storage).save(&state)?;
+    save_initial_settings(&mut deps.storage)?;
+    timestamps(&mut deps.storage).save(&recent_times)?;
+    store_header(&mut deps.storage, state.start_height, &start_record)?;
+
+    let mut logs = vec![log("action", "init"), log("submitter", &env.message.sender)];
+    logs.extend(tip_logs(&state));
+    Ok(InitResponse {
+        messages: vec![],
+        log: logs,
+    })
+}
+
+// Saves the admins, rewards, callbacks and checkpoints of a new contract.
+fn save_initial_settings<S: Storage>(storage: &mut S) -> StdResult<()> {
+    admins(storage).save(&vec![])?;
+    rewards(storage).save(&Rewards {
+        per_header: Uint128::zero(),
+        max_per_update: Uint128::zero(),
+        pool: Uint128::zero(),
+        total_paid: Uint128::zero(),
+    })?;
+    pending_callbacks(storage).save(&vec![])?;
+    checkpoint_heights(storage).save(&vec![])
+}
+
+// Upgrades the stored state from the layout of an older version of the contract,
+// one version at a time.
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    msg: MigrateMsg,
+) -> StdResult<MigrateResponse> {
+    let stored_version = version_read(&deps.storage).may_load()?.unwrap_or(0);
+    if stored_version > CONTRACT_VERSION {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Cannot migrate from version {} to older version {}",
+                stored_version, CONTRACT_VERSION
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    if stored_version < 1 {
+        match msg.from_v0 {
+            Some(from_v0) => migrate_from_v0(&mut deps.storage, from_v0)?,
+            None => {
+                return Err(StdError::GenericErr {
+                    msg: "from_v0 is required to migrate from version 0".to_string(),
+                    backtrace: Option::Some(Backtrace::generate()),
+                });
+            }
+        }
+    }
+    if stored_version < 2 {
+        // Contracts before version 2 followed mainnet, where every block in an
+        // epoch has the same bits.
+        config(&mut deps.storage).update(|mut state| {
+            state.network = Network::Mainnet;
+            state.epoch_bits = state.curr_bits;
+            Ok(state)
+        })?;
+    }
+    if stored_version < 3 {
+        checkpoint_heights(&mut deps.storage).save(&vec![])?;
+    }
+    if stored_version < 7 {
+        config(&mut deps.storage).update(|mut state| {
+            state.stale_threshold = DEFAULT_STALE_THRESHOLD;
+            Ok(state)
+        })?;
+    }
+    if stored_version < 8 {
+        // Older versions only removed the record that fell out of the retained range
+        // when storing a new one, so lowering the retention left older records behind
+        // with a missing height above them, and raising it left heights missing below
+        // the retained records. The history starts above the highest missing height,
+        // and records left below the retained range are pruned by the following updates.
+        let mut state = config_read(&deps.storage).load()?;
+        let tip_height = state.start_height + state.curr_offset;
+        state.oldest_height = find_oldest_header(&deps.storage, state.start_height, tip_height)?;
+        config(&mut deps.storage).save(&state)?;
+    }
+
+    version(&mut deps.storage).save(&CONTRACT_VERSION)?;
+    Ok(MigrateResponse::default())
+}
+
+// Version 0 only stored the current hash, so the rest of the current block header's
+// details are passed in, and its record starts the header history.
+fn migrate_from_v0<S: Storage>(storage: &mut S, msg: MigrateFromV0) -> StdResult<()> {
+    let old = config_v0_read(storage).load()?;
+    let chainwork = format!("{:x}", parse_chainwork(&msg.curr_chainwork)?);
+    let state = State {
+        start_height: old.start_height,
+        curr_offset: old.curr_offset,
+        curr_hash: old.curr_hash,
+        threshold_difficulty: old.threshold_difficulty,
+        min_update_length: old.min_update_length,
+        owner: old.owner,
+        pending_owner: None,
+        curr_bits: msg.curr_bits,
+        curr_time: msg.curr_time,
+        epoch_start_time: msg.epoch_start_time,
+        header_retention: msg.header_retention,
+        chainwork: chainwork.clone(),
+        paused: false,
+        network: Network::Mainnet,
+        epoch_bits: msg.curr_bits,
+        max_update_length: 0,
+        finality_depth: 0,
+        last_update_block_height: 0,
+        last_update_block_time: 0,
+        stale_threshold: DEFAULT_STALE_THRESHOLD,
+        oldest_height: old.start_height + old.curr_offset,
+    };
+
+    let mut recent_times = msg.prev_timestamps;
+    push_timestamp(&mut recent_times, msg.curr_time);
+    let record = HeaderRecord {
+        hash: state.curr_hash.clone(),
+        time: msg.curr_time,
+        bits: msg.curr_bits,
+        merkle_root: None,
+        chainwork,
+        median_time_past: Some(median_time_past(&recent_times)),
+        max_time: recent_times.iter().max().copied(),
+    };
 
-    Ok(InitResponse::default())
+    config(storage).save(&state)?;
+    save_initial_settings(storage)?;
+    timestamps(storage).save(&recent_times)?;
+    store_header(storage, state.oldest_height, &record)
 }
 
 pub fn handle<S: Storage, A: Api, Q: Querier>(
@@ -44,8 +245,80 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     match msg {
         HandleMsg::UpdateBlockOffset {
             block_headers: blocks,
-        } => try_update_offset(deps, env, blocks),
+            accept_prefix,
+            skip_callbacks,
+        } => try_update_offset(
+            deps,
+            env,
+            decode_headers(&blocks)?,
+            accept_prefix.unwrap_or(false),
+            skip_callbacks.unwrap_or(false),
+        ),
+        HandleMsg::UpdateBlockOffsetPacked {
+            headers,
+            accept_prefix,
+            skip_callbacks,
+        } => try_update_offset(
+            deps,
+            env,
+            decode_packed_headers(headers.as_slice())?,
+            accept_prefix.unwrap_or(false),
+            skip_callbacks.unwrap_or(false),
+        ),
         HandleMsg::ResetState { new_state } => try_reset_state(deps, env, new_state),
+        HandleMsg::SubmitFork {
+            fork_point_height,
+            block_headers,
+            skip_callbacks,
+        } => try_submit_fork(
+            deps,
+            env,
+            fork_point_height,
+            decode_headers(&block_headers)?,
+            skip_callbacks.unwrap_or(false),
+        ),
+        HandleMsg::TransferOwnership { new_owner } => try_transfer_ownership(deps, env, new_owner),
+        HandleMsg::AcceptOwnership {} => try_accept_ownership(deps, env),
+        HandleMsg::SetAdmin {
+            address,
+            permissions,
+        } => try_set_admin(deps, env, address, permissions),
+        HandleMsg::UpdateConfig {
+            min_difficulty_bits,
+            min_update_length,
+            header_retention,
+            max_update_length,
+            finality_depth,
+            stale_threshold,
+        } => try_update_config(
+            deps,
+            env,
+            ConfigUpdate {
+                min_difficulty_bits,
+                min_update_length,
+                header_retention,
+                max_update_length,
+                finality_depth,
+                stale_threshold,
+            },
+        ),
+        HandleMsg::Pause {} => try_set_paused(deps, env, true),
+        HandleMsg::Unpause {} => try_set_paused(deps, env, false),
+        HandleMsg::Fund {} => try_fund(deps, env),
+        HandleMsg::SetRewards {
+            reward_per_header,
+            max_reward_per_update,
+        } => try_set_rewards(deps, env, reward_per_header, max_reward_per_update),
+        HandleMsg::RegisterCallback {
+            condition,
+            contract_addr,
+            callback_code_hash,
+            msg,
+        } => try_register_callback(deps, env, condition, contract_addr, callback_code_hash, msg),
+        HandleMsg::CancelCallback { id } => try_cancel_callback(deps, env, id),
+        HandleMsg::TriggerCallbacks { id } => try_trigger_callbacks(deps, env, id),
+        HandleMsg::AddCheckpoint { height, hash } => try_add_checkpoint(deps, env, height, hash),
+        HandleMsg::RemoveCheckpoint { height } => try_remove_checkpoint(deps, env, height),
     }
 }
 
@@ -55,8 +328,11 @@ pub fn try_reset_state<S: Storage, A: Api, Q: Querier>(
     msg: InitMsg,
 ) -> StdResult<HandleResponse> {
     let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
-    config(&mut deps.storage).update(|mut state| {
-        if sender_address_raw != state.owner {
+    let admins = admins_read(&deps.storage).load()?;
+    let recent_times = initial_timestamps(&msg);
+    let start_record = start_header_record(&msg)?;
+    let state = config(&mut deps.storage).update(|mut state| {
+        if !has_permission(&state, &admins, &sender_address_raw, Permission::Reset) {
             return Err(StdError::Unauthorized { backtrace: None });
         }
         state.curr_hash = msg.start_hash;
@@ -64,421 +340,4733 @@ pub fn try_reset_state<S: Storage, A: Api, Q: Querier>(
         state.start_height = msg.start_height;
         state.threshold_difficulty = format!("{:x}", bits_to_difficulty(msg.min_difficulty_bits));
         state.min_update_length = msg.min_update_length;
+        state.curr_bits = msg.start_bits;
+        state.curr_time = msg.start_time;
+        state.epoch_start_time = msg.epoch_start_time;
+        state.header_retention = msg.header_retention;
+        state.chainwork = start_record.chainwork.clone();
+        state.network = msg.network.unwrap_or_default();
+        state.epoch_bits = msg.epoch_start_bits.unwrap_or(msg.start_bits);
+        state.max_update_length = msg.max_update_length.unwrap_or(0);
+        state.finality_depth = msg.finality_depth.unwrap_or(0);
+        state.last_update_block_height = env.block.height;
+        state.last_update_block_time = env.block.time;
+        state.stale_threshold = msg.stale_threshold.unwrap_or(DEFAULT_STALE_THRESHOLD);
+        state.oldest_height = msg.start_height;
+        check_update_length_limits(&state)?;
+        check_finality_depth(&state)?;
         Ok(state)
     })?;
-    Ok(HandleResponse::default())
+    timestamps(&mut deps.storage).save(&recent_times)?;
+    store_header(&mut deps.storage, state.start_height, &start_record)?;
+
+    let mut logs = vec![
+        log("action", "reset_state"),
+        log("submitter", &env.message.sender),
+    ];
+    logs.extend(tip_logs(&state));
+    Ok(HandleResponse {
+        messages: vec![],
+        log: logs,
+        data: Some(to_binary(&update_result(&state, 0, 0, None))?),
+    })
 }
 
-// Double hashes a hex string and returns a hex string.
-pub fn double_hash_hex(hex_str: &String) -> String {
-    let inp: Vec<u8> = hex_str.from_hex().unwrap();
-    let first: [u8; 32] = Sha256::digest(&inp[..]).into();
-    let second: [u8; 32] = Sha256::digest(&first).into();
-    return second.to_hex();
+pub fn try_transfer_ownership<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    new_owner: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let new_owner_raw = deps.api.canonical_address(&new_owner)?;
+    config(&mut deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(StdError::Unauthorized { backtrace: None });
+        }
+        state.pending_owner = Some(new_owner_raw);
+        Ok(state)
+    })?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "transfer_ownership"),
+            log("submitter", &env.message.sender),
+            log("pending_owner", &new_owner),
+        ],
+        data: None,
+    })
 }
 
-// bits is a u32 as a hex string in little endian format.
-pub fn parse_bits(bits: &str) -> Result<u32, ParseIntError> {
-    // This will read the value in as big endian.
-    let parsed = u32::from_str_radix(bits, 16);
-    let parsed = match parsed {
-        Ok(num) => num,
-        Err(error) => return Result::Err(error),
-    };
-    // We assume bits was passed in as little endian, so swap to get the actual value.
-    parsed.swap_bytes();
-    return Result::Ok(parsed);
+pub fn try_accept_ownership<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    config(&mut deps.storage).update(|mut state| {
+        if state.pending_owner != Some(sender_address_raw) {
+            return Err(StdError::Unauthorized { backtrace: None });
+        }
+        state.owner = state.pending_owner.take().unwrap();
+        Ok(state)
+    })?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "accept_ownership"),
+            log("submitter", &env.message.sender),
+        ],
+        data: None,
+    })
 }
 
-// Convert bits encoding into a difficulty number.
-// See https://en.bitcoin.it/wiki/Difficulty.
-// From https://bitcoin.stackexchange.com/questions/30467/what-are-the-equations-to-convert-between-bits-and-difficulty.
-pub fn bits_to_difficulty(n_compact: u32) -> U256 {
-    let n_size: u32 = n_compact >> 24;
-    let mut n_word: u32 = n_compact & 0x007fffff;
-    let mut diff: U256;
-    if n_size <= 3 {
-        n_word >>= 8 * (3 - n_size);
-        diff = U256::from(n_word);
-    } else {
-        diff = U256::from(n_word);
-        diff <<= 8 * (n_size - 3);
+pub fn try_set_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    permissions: Vec<Permission>,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let state = config_read(&deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(StdError::Unauthorized { backtrace: None });
     }
-    diff
+
+    let address_raw = deps.api.canonical_address(&address)?;
+    let mut unique_permissions = vec![];
+    for permission in permissions {
+        if !unique_permissions.contains(&permission) {
+            unique_permissions.push(permission);
+        }
+    }
+    let permission_names: Vec<&str> = unique_permissions.iter().map(|p| p.name()).collect();
+    admins(&mut deps.storage).update(|mut admins| {
+        admins.retain(|admin| admin.address != address_raw);
+        if !unique_permissions.is_empty() {
+            admins.push(Admin {
+                address: address_raw,
+                permissions: unique_permissions,
+            });
+        }
+        Ok(admins)
+    })?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_admin"),
+            log("submitter", &env.message.sender),
+            log("address", &address),
+            log("permissions", permission_names.join(",")),
+        ],
+        data: None,
+    })
 }
 
-// Convenience function to go from little to big endian for a even length hex string.
-pub fn flip_bytes_in_str(hex_str: &String) -> String {
-    let mut inp: Vec<u8> = hex_str.from_hex().unwrap();
-    inp.reverse();
-    inp.to_hex()
+// The settings changed by UpdateConfig. Values that are None stay the same.
+pub struct ConfigUpdate {
+    pub min_difficulty_bits: Option<u32>,
+    pub min_update_length: Option<u32>,
+    pub header_retention: Option<u32>,
+    pub max_update_length: Option<u32>,
+    pub finality_depth: Option<u32>,
+    pub stale_threshold: Option<u64>,
 }
 
-// Verifies header values. If successful, updates the offset
-// and the current block header hash.
-pub fn try_update_offset<S: Storage, A: Api, Q: Querier>(
+pub fn try_update_config<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
-    headers: Vec<String>,
+    env: Env,
+    update: ConfigUpdate,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let admins = admins_read(&deps.storage).load()?;
+    let mut state = config(&mut deps.storage).update(|mut state| {
+        if !has_permission(&state, &admins, &sender_address_raw, Permission::Configure) {
+            return Err(StdError::Unauthorized { backtrace: None });
+        }
+        if let Some(bits) = update.min_difficulty_bits {
+            state.threshold_difficulty = format!("{:x}", bits_to_difficulty(bits));
+        }
+        if let Some(length) = update.min_update_length {
+            state.min_update_length = length;
+        }
+        if let Some(retention) = update.header_retention {
+            state.header_retention = retention;
+        }
+        if let Some(length) = update.max_update_length {
+            state.max_update_length = length;
+        }
+        if let Some(depth) = update.finality_depth {
+            state.finality_depth = depth;
+        }
+        if let Some(threshold) = update.stale_threshold {
+            state.stale_threshold = threshold;
+        }
+        check_update_length_limits(&state)?;
+        check_finality_depth(&state)?;
+        Ok(state)
+    })?;
+    // Records that fall out of a lowered retention are removed a page at a time, here
+    // and in the following updates.
+    let tip_height = state.start_height + state.curr_offset;
+    state.oldest_height = prune_headers(
+        &mut deps.storage,
+        state.oldest_height,
+        tip_height,
+        state.header_retention,
+        MAX_PRUNED_HEADERS,
+    )?;
+    config(&mut deps.storage).save(&state)?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "update_config"),
+            log("submitter", &env.message.sender),
+            log("min_difficulty", &state.threshold_difficulty),
+            log("min_update_length", state.min_update_length),
+            log("max_update_length", state.max_update_length),
+            log("header_retention", state.header_retention),
+            log("finality_depth", state.finality_depth),
+            log("stale_threshold", state.stale_threshold),
+        ],
+        data: None,
+    })
+}
+
+// The maximum update length, if there is one, can't be less than the minimum.
+fn check_update_length_limits(state: &State) -> StdResult<()> {
+    if state.max_update_length > 0 && state.max_update_length < state.min_update_length {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Maximum update length {} is less than the minimum update length {}",
+                state.max_update_length, state.min_update_length
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    Ok(())
+}
+
+// The finalized tip has to stay in the retained header history.
+fn check_finality_depth(state: &State) -> StdResult<()> {
+    if state.header_retention > 0 && state.finality_depth >= state.header_retention {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Finality depth {} must be less than the header retention {}",
+                state.finality_depth, state.header_retention
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    Ok(())
+}
+
+pub fn try_set_paused<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    paused: bool,
 ) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let admins = admins_read(&deps.storage).load()?;
     config(&mut deps.storage).update(|mut state| {
-        // Check that the number of block header hashes passed in is large enough.
-        let num_headers = u32::try_from(headers.len()).unwrap();
-        if state.min_update_length > num_headers {
+        if !has_permission(&state, &admins, &sender_address_raw, Permission::Pause) {
+            return Err(StdError::Unauthorized { backtrace: None });
+        }
+        state.paused = paused;
+        Ok(state)
+    })?;
+    let action = if paused { "pause" } else { "unpause" };
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", action), log("submitter", &env.message.sender)],
+        data: None,
+    })
+}
+
+// Adds the funds sent with the message to the reward pool.
+pub fn try_fund<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut amount: u128 = 0;
+    for coin in env.message.sent_funds.iter() {
+        if coin.denom != REWARD_DENOM {
+            return Err(StdError::GenericErr {
+                msg: format!(
+                    "The reward pool can only be funded with {}, not {}",
+                    REWARD_DENOM, coin.denom
+                ),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+        amount += coin.amount.u128();
+    }
+    if amount == 0 {
+        return Err(StdError::GenericErr {
+            msg: format!("No {} was sent to fund the reward pool", REWARD_DENOM),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    rewards(&mut deps.storage).update(|mut rewards| {
+        rewards.pool = Uint128(rewards.pool.u128() + amount);
+        Ok(rewards)
+    })?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "fund"),
+            log("submitter", &env.message.sender),
+            log("amount", amount),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_set_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    reward_per_header: Uint128,
+    max_reward_per_update: Uint128,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let state = config_read(&deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(StdError::Unauthorized { backtrace: None });
+    }
+    rewards(&mut deps.storage).update(|mut rewards| {
+        rewards.per_header = reward_per_header;
+        rewards.max_per_update = max_reward_per_update;
+        Ok(rewards)
+    })?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_rewards"),
+            log("submitter", &env.message.sender),
+            log("reward_per_header", reward_per_header),
+            log("max_reward_per_update", max_reward_per_update),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_add_checkpoint<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    height: u32,
+    hash: String,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let state = config_read(&deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(StdError::Unauthorized { backtrace: None });
+    }
+    let hash = match BlockHash::from_internal_hex(&hash) {
+        Ok(hash) => hash.to_internal_hex(),
+        Err(err) => {
+            return Err(StdError::GenericErr {
+                msg: format!("Invalid checkpoint hash: {}", err),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+    };
+
+    // A checkpoint that the current chain doesn't match would stop all updates,
+    // so the chain has to be reset or replaced first.
+    if let Some(record) = stored_header(&deps.storage, &state, height)? {
+        if record.hash != hash {
             return Err(StdError::GenericErr {
                 msg: format!(
-                    "Number of blocks provided ({}) is less than minimum required ({})",
-                    num_headers, state.min_update_length
+                    "Checkpoint {} at height {} does not match the stored block header hash {}",
+                    hash, height, record.hash
                 ),
                 backtrace: Option::Some(Backtrace::generate()),
             });
         }
+    }
 
-        // The first header must reference the current hash stored by the contract.
-        let mut prev_hash = state.curr_hash;
+    add_checkpoint(
+        &mut deps.storage,
+        &Checkpoint {
+            height,
+            hash: hash.clone(),
+        },
+    )?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "add_checkpoint"),
+            log("submitter", &env.message.sender),
+            log("height", height),
+            log("hash", hash),
+        ],
+        data: None,
+    })
+}
 
-        // Verify every header.
-        for header in headers.iter() {
-            // Check the header length.
-            if header.len() != BLOCK_HEADER_LEN {
-                return Err(StdError::GenericErr {
-                    msg: format!(
-                        "Encoded block header length is {}, must be {}",
-                        header.len(),
-                        BLOCK_HEADER_LEN
-                    ),
-                    backtrace: Option::Some(Backtrace::generate()),
-                });
-            }
+pub fn try_remove_checkpoint<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    height: u32,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let state = config_read(&deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(StdError::Unauthorized { backtrace: None });
+    }
+    if !remove_checkpoint(&mut deps.storage, height)? {
+        return Err(StdError::NotFound {
+            kind: format!("Checkpoint at height {}", height),
+            backtrace: None,
+        });
+    }
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "remove_checkpoint"),
+            log("submitter", &env.message.sender),
+            log("height", height),
+        ],
+        data: None,
+    })
+}
 
-            // Check the difficulty bits in the header against the
-            // difficulty threshold stored by the contract.
-            let difficulty_bits = &header[144..144 + 8];
-            let parsed_res = parse_bits(difficulty_bits);
-            let parsed = match parsed_res {
-                Ok(res) => res,
-                Err(err) => {
-                    return Err(StdError::GenericErr {
-                        msg: format!(
-                            "Could not parse difficulty bits \"{}\" into u32: {}",
-                            difficulty_bits, err
-                        ),
-                        backtrace: Option::Some(Backtrace::generate()),
-                    });
-                }
-            };
-            let block_diff = bits_to_difficulty(parsed.swap_bytes());
-            let thresh_diff_res = U256::from_str_radix(&state.threshold_difficulty, 16);
-            let thresh_diff = match thresh_diff_res {
-                Ok(res) => res,
-                Err(err) => {
-                    return Err(StdError::GenericErr {
-                        msg: format!("Could not convert difficulty bits into U256: {}", err),
-                        backtrace: Option::Some(Backtrace::generate()),
-                    });
-                }
-            };
-            if block_diff > thresh_diff {
+// Pays the sender for the block headers it submitted out of the reward pool.
+// The reward is limited by the per update maximum and by what's left in the pool.
+fn pay_reward<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: &Env,
+    num_headers: usize,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut pool = rewards_read(&deps.storage).load()?;
+    let mut amount = pool.per_header.u128().saturating_mul(num_headers as u128);
+    if !pool.max_per_update.is_zero() && amount > pool.max_per_update.u128() {
+        amount = pool.max_per_update.u128();
+    }
+    if amount > pool.pool.u128() {
+        amount = pool.pool.u128();
+    }
+    if amount == 0 {
+        return Ok(vec![]);
+    }
+
+    pool.pool = Uint128(pool.pool.u128() - amount);
+    pool.total_paid = Uint128(pool.total_paid.u128() + amount);
+    rewards(&mut deps.storage).save(&pool)?;
+
+    let relayer = deps.api.canonical_address(&env.message.sender)?;
+    let paid = relayer_rewards_read(&deps.storage)
+        .may_load(relayer.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    relayer_rewards(&mut deps.storage).save(relayer.as_slice(), &Uint128(paid.u128() + amount))?;
+
+    Ok(vec![CosmosMsg::Bank(BankMsg::Send {
+        from_address: env.contract.address.clone(),
+        to_address: env.message.sender.clone(),
+        amount: coins(amount, REWARD_DENOM),
+    })])
+}
+
+pub fn try_register_callback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    condition: CallbackCondition,
+    contract_addr: HumanAddr,
+    callback_code_hash: String,
+    msg: Binary,
+) -> StdResult<HandleResponse> {
+    // Updates only execute callbacks that they make due.
+    let state = config_read(&deps.storage).load()?;
+    let (tip_height, median_time) = callback_clock(&deps.storage, &state)?;
+    if callback_due(&condition, tip_height, median_time) {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Callback condition {:?} is already met by the tip at height {}",
+                condition, tip_height
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let mut registered = 0;
+    for id in pending_callbacks_read(&deps.storage).load()? {
+        let callback = callbacks_read(&deps.storage).load(&callback_key(id))?;
+        if callback.registrant == sender_address_raw {
+            registered += 1;
+        }
+    }
+    if registered >= MAX_CALLBACKS_PER_REGISTRANT {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "{} already has {} pending callbacks, the most allowed",
+                env.message.sender, MAX_CALLBACKS_PER_REGISTRANT
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    let callback = Callback {
+        id: 0,
+        registrant: sender_address_raw,
+        condition,
+        contract_addr,
+        callback_code_hash,
+        msg,
+    };
+    let id = add_callback(&mut deps.storage, callback)?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_callback"),
+            log("submitter", &env.message.sender),
+            log("callback_id", id),
+        ],
+        data: Some(to_binary(&RegisterCallbackResponse { id })?),
+    })
+}
+
+pub fn try_cancel_callback<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: u64,
+) -> StdResult<HandleResponse> {
+    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    let callback = match callbacks_read(&deps.storage).may_load(&callback_key(id))? {
+        Some(callback) => callback,
+        None => {
+            return Err(StdError::NotFound {
+                kind: format!("Callback {}", id),
+                backtrace: None,
+            });
+        }
+    };
+    // The owner can cancel callbacks too, in case one keeps failing.
+    let state = config_read(&deps.storage).load()?;
+    if sender_address_raw != callback.registrant && sender_address_raw != state.owner {
+        return Err(StdError::Unauthorized { backtrace: None });
+    }
+    remove_callback(&mut deps.storage, id)?;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "cancel_callback"),
+            log("submitter", &env.message.sender),
+            log("callback_id", id),
+        ],
+        data: None,
+    })
+}
+
+// Executes due callbacks that updates left over, either the one with the id, or the
+// oldest ones. Executing a single one lets the others be executed when one of them
+// keeps failing.
+pub fn try_trigger_callbacks<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    id: Option<u64>,
+) -> StdResult<HandleResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let (tip_height, median_time) = callback_clock(&deps.storage, &state)?;
+    let callbacks = match id {
+        Some(id) => match callbacks_read(&deps.storage).may_load(&callback_key(id))? {
+            Some(callback) if callback_due(&callback.condition, tip_height, median_time) => {
+                vec![callback]
+            }
+            Some(_) => {
                 return Err(StdError::GenericErr {
-                    msg: format!(
-                        "Block difficulty {} cannot be greater than threshold {}",
-                        format!("{:x}", block_diff),
-                        format!("{:x}", thresh_diff)
-                    ),
+                    msg: format!("Callback {} is not due yet", id),
                     backtrace: Option::Some(Backtrace::generate()),
                 });
             }
-
-            // Check that the header references the correct previous header hash.
-            let prev_block = &header[8..8 + 64];
-            if prev_block != prev_hash {
-                return Err(StdError::GenericErr {
-                    msg: format!(
-                        "Previous block header hash {} is not equal to value in header {}",
-                        prev_hash, prev_block
-                    ),
-                    backtrace: Option::Some(Backtrace::generate()),
+            None => {
+                return Err(StdError::NotFound {
+                    kind: format!("Callback {}", id),
+                    backtrace: None,
                 });
             }
+        },
+        None => due_callbacks(&deps.storage, |condition| {
+            callback_due(condition, tip_height, median_time)
+        })?,
+    };
+    if callbacks.is_empty() {
+        return Err(StdError::GenericErr {
+            msg: "No callbacks are due".to_string(),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
 
-            // Compute the target hash and update the prev_hash with it.
-            prev_hash = double_hash_hex(&header);
+    let messages = execute_callbacks(&mut deps.storage, callbacks)?;
+    Ok(HandleResponse {
+        log: vec![
+            log("action", "trigger_callbacks"),
+            log("submitter", &env.message.sender),
+            log("callbacks_executed", messages.len()),
+        ],
+        messages,
+        data: None,
+    })
+}
 
-            // Check the difficulty of the target hash against the block difficulty.
-            let flipped = flip_bytes_in_str(&prev_hash);
-            let target_res = U256::from_str_radix(&flipped, 16);
-            let target = match target_res {
-                Ok(res) => res,
-                Err(err) => {
-                    return Err(StdError::GenericErr {
-                        msg: format!(
-                            "Could not convert target hash \"{}\" into U256: {}",
-                            flipped, err
-                        ),
-                        backtrace: Option::Some(Backtrace::generate()),
-                    });
-                }
-            };
-            if target > block_diff {
-                return Err(StdError::GenericErr {
-                    msg: format!(
-                        "Block header hash {} must be less than block difficulty {}",
-                        format!("{:x}", target),
-                        format!("{:x}", block_diff)
-                    ),
-                    backtrace: Option::Some(Backtrace::generate()),
-                });
-            }
-        }
+// The tip height and median time past that callback conditions are checked against.
+fn callback_clock<S: Storage>(storage: &S, state: &State) -> StdResult<(u32, u32)> {
+    let tip_height = state.start_height + state.curr_offset;
+    Ok((
+        tip_height,
+        median_time_past(&timestamps_read(storage).load()?),
+    ))
+}
 
-        state.curr_hash = prev_hash;
-        state.curr_offset += num_headers;
-        Ok(state)
+// Removes up to MAX_CALLBACKS_PER_UPDATE callbacks that became due since the tip
+// was at before, oldest first, and returns the messages that execute them. Callbacks
+// that were already due were skipped or left over by earlier updates, and are left
+// for TriggerCallbacks.
+fn execute_newly_due_callbacks<S: Storage>(
+    storage: &mut S,
+    state: &State,
+    before: (u32, u32),
+) -> StdResult<Vec<CosmosMsg>> {
+    let (tip_height, median_time) = callback_clock(storage, state)?;
+    let callbacks = due_callbacks(storage, |condition| {
+        callback_due(condition, tip_height, median_time)
+            && !callback_due(condition, before.0, before.1)
     })?;
-
-    // TODO: what is this for?
-    Ok(HandleResponse::default())
+    execute_callbacks(storage, callbacks)
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetContractInfo {} => to_binary(&query_info(deps)?),
+// Removes the callbacks, which are executed once at most, and returns the messages
+// that execute them.
+fn execute_callbacks<S: Storage>(
+    storage: &mut S,
+    callbacks: Vec<Callback>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages = vec![];
+    for callback in callbacks {
+        remove_callback(storage, callback.id)?;
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: callback.contract_addr,
+            callback_code_hash: callback.callback_code_hash,
+            msg: callback.msg,
+            send: vec![],
+        }));
     }
+    Ok(messages)
 }
 
-fn query_info<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<InfoResponse> {
-    let state = config_read(&deps.storage).load()?;
-    Ok(InfoResponse {
-        start_height: state.start_height,
-        min_difficulty: state.threshold_difficulty,
-        curr_hash: state.curr_hash,
-        curr_offset: state.curr_offset,
-        min_update_length: state.min_update_length,
-    })
+// Whether the callback's condition is met by the current tip.
+fn callback_due(condition: &CallbackCondition, tip_height: u32, median_time: u32) -> bool {
+    match condition {
+        CallbackCondition::Height(height) => tip_height >= *height,
+        CallbackCondition::Time(time) => median_time >= *time,
+    }
 }
 
-#[cfg(test)]
-mod tests {
+// Up to MAX_CALLBACKS_PER_UPDATE pending callbacks whose condition passes the check,
+// in the order they were registered.
+fn due_callbacks<S: Storage, F: Fn(&CallbackCondition) -> bool>(
+    storage: &S,
+    due: F,
+) -> StdResult<Vec<Callback>> {
+    let mut callbacks = vec![];
+    for id in pending_callbacks_read(storage).load()? {
+        if callbacks.len() >= MAX_CALLBACKS_PER_UPDATE {
+            break;
+        }
+        let callback = callbacks_read(storage).load(&callback_key(id))?;
+        if due(&callback.condition) {
+            callbacks.push(callback);
+        }
+    }
+    Ok(callbacks)
+}
+
+// Whether the address is the owner, or an admin that was granted the permission.
+fn has_permission(
+    state: &State,
+    admins: &[Admin],
+    address: &CanonicalAddr,
+    permission: Permission,
+) -> bool {
+    *address == state.owner
+        || admins
+            .iter()
+            .any(|admin| admin.address == *address && admin.permissions.contains(&permission))
+}
+
+// The header record for the start block. Its merkle root isn't known.
+fn start_header_record(msg: &InitMsg) -> StdResult<HeaderRecord> {
+    let recent_times = initial_timestamps(msg);
+    Ok(HeaderRecord {
+        hash: msg.start_hash.clone(),
+        time: msg.start_time,
+        bits: msg.start_bits,
+        merkle_root: None,
+        chainwork: format!("{:x}", parse_chainwork(&msg.start_chainwork)?),
+        median_time_past: Some(median_time_past(&recent_times)),
+        max_time: recent_times.iter().max().copied(),
+    })
+}
+
+// Builds the rolling window of recent timestamps from the ones passed in at init,
+// ending with the start block's timestamp.
+fn initial_timestamps(msg: &InitMsg) -> Vec<u32> {
+    let mut recent_times = msg.prev_timestamps.clone();
+    push_timestamp(&mut recent_times, msg.start_time);
+    recent_times
+}
+
+// Appends a timestamp to the rolling window, dropping the oldest ones
+// so that at most MEDIAN_TIME_SPAN remain.
+fn push_timestamp(recent_times: &mut Vec<u32>, time: u32) {
+    recent_times.push(time);
+    if recent_times.len() > MEDIAN_TIME_SPAN {
+        let excess = recent_times.len() - MEDIAN_TIME_SPAN;
+        recent_times.drain(..excess);
+    }
+}
+
+// Computes the median of the given timestamps, see GetMedianTimePast in Bitcoin Core.
+// Uses whatever timestamps are available if there are fewer than MEDIAN_TIME_SPAN.
+pub fn median_time_past(recent_times: &[u32]) -> u32 {
+    let mut sorted = recent_times.to_vec();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+// Decodes a 32 byte hash from a hex string.
+fn decode_hash(hash: &str) -> StdResult<[u8; 32]> {
+    let decoded: Vec<u8> = match hash.from_hex() {
+        Ok(res) => res,
+        Err(err) => {
+            return Err(StdError::GenericErr {
+                msg: format!("Could not decode hash \"{}\": {}", hash, err),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+    };
+    if decoded.len() != 32 {
+        return Err(StdError::GenericErr {
+            msg: format!("Hash \"{}\" must be 32 bytes", hash),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    let mut res = [0u8; 32];
+    res.copy_from_slice(&decoded);
+    Ok(res)
+}
+
+// Checks whether 64 bytes could be the serialization of a transaction with one input
+// and one output, the only kind of transaction that is 64 bytes long. The hash of such
+// a transaction can't be told apart from an inner node of the merkle tree.
+pub fn could_be_transaction(data: &[u8; 64]) -> bool {
+    // version (4), input count (1), prevout (36), script length (1)
+    if data[4] != 1 {
+        return false;
+    }
+    let script_sig_len = data[41] as usize;
+    if script_sig_len > 4 {
+        return false;
+    }
+    // script (script_sig_len), sequence (4), output count (1), value (8), script length (1)
+    let output_count_pos = 42 + script_sig_len + 4;
+    if data[output_count_pos] != 1 {
+        return false;
+    }
+    // The output script and the 4 byte lock time take up the rest.
+    data[output_count_pos + 9] as usize == 4 - script_sig_len
+}
+
+// Computes the merkle root from a transaction hash and its merkle branch.
+// Bits of index determine whether each branch hash is on the left or the right.
+pub fn compute_merkle_root(
+    txid: [u8; 32],
+    merkle_branch: &[[u8; 32]],
+    index: u32,
+) -> StdResult<[u8; 32]> {
+    if merkle_branch.len() > MAX_MERKLE_BRANCH_LEN {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Merkle branch of {} hashes is longer than the maximum of {}",
+                merkle_branch.len(),
+                MAX_MERKLE_BRANCH_LEN
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    if u64::from(index) >> merkle_branch.len() != 0 {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Transaction index {} is out of range for a merkle branch of {} hashes",
+                index,
+                merkle_branch.len()
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    let mut hash = txid;
+    let mut pos = index;
+    for (level, sibling) in merkle_branch.iter().enumerate() {
+        let mut pair = [0u8; 64];
+        if pos & 1 == 1 {
+            pair[..32].copy_from_slice(sibling);
+            pair[32..].copy_from_slice(&hash);
+        } else {
+            pair[..32].copy_from_slice(&hash);
+            pair[32..].copy_from_slice(sibling);
+        }
+        // If the bottom pair could be a transaction, the "transaction" being proven
+        // might really be half of a 64 byte transaction.
+        if level == 0 && could_be_transaction(&pair) {
+            return Err(StdError::GenericErr {
+                msg: "Merkle branch is ambiguous, the transaction and its sibling could be a 64 byte transaction".to_string(),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+        hash = double_sha256(&pair);
+        pos >>= 1;
+    }
+    Ok(hash)
+}
+
+// Convert bits encoding into a difficulty number.
+// See https://en.bitcoin.it/wiki/Difficulty.
+// From https://bitcoin.stackexchange.com/questions/30467/what-are-the-equations-to-convert-between-bits-and-difficulty.
+pub fn bits_to_difficulty(n_compact: u32) -> U256 {
+    let n_size: u32 = n_compact >> 24;
+    let mut n_word: u32 = n_compact & 0x007fffff;
+    let mut diff: U256;
+    if n_size <= 3 {
+        n_word >>= 8 * (3 - n_size);
+        diff = U256::from(n_word);
+    } else {
+        diff = U256::from(n_word);
+        diff <<= 8 * (n_size - 3);
+    }
+    diff
+}
+
+// Convert a difficulty number back into its compact bits encoding.
+// This is the inverse of bits_to_difficulty, see GetCompact in Bitcoin Core.
+pub fn difficulty_to_bits(diff: U256) -> u32 {
+    let mut n_size = (diff.bits() as u32 + 7) / 8;
+    let mut n_compact: u32 = if n_size <= 3 {
+        (diff.low_u64() << (8 * (3 - n_size))) as u32
+    } else {
+        (diff >> (8 * (n_size - 3))).low_u32()
+    };
+    // The 0x00800000 bit denotes the sign, so if it is already set divide the
+    // mantissa by 256 and increase the exponent.
+    if n_compact & 0x00800000 != 0 {
+        n_compact >>= 8;
+        n_size += 1;
+    }
+    n_compact | (n_size << 24)
+}
+
+// Computes the bits required for the first block of a new difficulty epoch.
+// first_time is the timestamp of the first block in the epoch that just ended
+// and last_time the timestamp of its last block. The adjustment is clamped to
+// a factor of 4 in either direction, see CalculateNextWorkRequired in Bitcoin Core.
+pub fn retarget_bits(params: &NetworkParams, bits: u32, first_time: u32, last_time: u32) -> u32 {
+    let min_timespan = i64::from(params.target_timespan / 4);
+    let max_timespan = i64::from(params.target_timespan * 4);
+    let mut actual_timespan = i64::from(last_time) - i64::from(first_time);
+    if actual_timespan < min_timespan {
+        actual_timespan = min_timespan;
+    }
+    if actual_timespan > max_timespan {
+        actual_timespan = max_timespan;
+    }
+
+    let pow_limit = bits_to_difficulty(params.pow_limit_bits);
+    // Targets easier than the pow limit can overflow, but they're clamped to it anyway.
+    let mut new_diff =
+        match bits_to_difficulty(bits).checked_mul(U256::from(actual_timespan as u64)) {
+            Some(product) => product / U256::from(params.target_timespan),
+            None => pow_limit,
+        };
+    if new_diff > pow_limit {
+        new_diff = pow_limit;
+    }
+    difficulty_to_bits(new_diff)
+}
+
+// The expected number of hashes needed to find a block with the given bits,
+// which is 2^256 / (target + 1). See GetBlockProof in Bitcoin Core.
+pub fn block_work(bits: u32) -> U256 {
+    let target = bits_to_difficulty(bits);
+    if target.is_zero() {
+        return U256::zero();
+    }
+    // 2^256 doesn't fit in a U256, but 2^256 / (target + 1) is equal to
+    // (2^256 - target - 1) / (target + 1) + 1.
+    (!target / (target + U256::one())) + U256::one()
+}
+
+// The block header that new block headers are verified against, along with
+// the context needed to verify them.
+pub struct ChainTip {
+    pub height: u32,
+    pub hash: String,
+    pub bits: u32,
+    pub time: u32,
+    pub epoch_start_time: u32,
+    // Bits of the first block in the epoch.
+    pub epoch_bits: u32,
+    // Timestamps of the most recent block headers, ending with this one.
+    pub recent_times: Vec<u32>,
+    // Greatest timestamp of this block and the blocks before it.
+    pub max_time: u32,
+    // Total work of the chain up to and including this block.
+    pub chainwork: U256,
+}
+
+// Decodes block headers given as 160 character hex strings.
+fn decode_headers(headers: &[String]) -> Result<Vec<BlockHeader>, ContractError> {
+    let mut decoded = Vec::with_capacity(headers.len());
+    for (index, header) in headers.iter().enumerate() {
+        // Check the header length.
+        if header.len() != BLOCK_HEADER_LEN {
+            return Err(ContractError::BadHeaderLength {
+                index,
+                length: header.len(),
+                expected: BLOCK_HEADER_LEN,
+            });
+        }
+        match BlockHeader::from_hex(header) {
+            Ok(res) => decoded.push(res),
+            Err(err) => {
+                return Err(ContractError::BadHeaderEncoding {
+                    index,
+                    reason: err.to_string(),
+                });
+            }
+        }
+    }
+    Ok(decoded)
+}
+
+// Decodes concatenated raw 80 byte block headers.
+fn decode_packed_headers(headers: &[u8]) -> Result<Vec<BlockHeader>, ContractError> {
+    if headers.len() % BLOCK_HEADER_SIZE != 0 {
+        // The leftover bytes are treated as a truncated last header.
+        return Err(ContractError::BadHeaderLength {
+            index: headers.len() / BLOCK_HEADER_SIZE,
+            length: headers.len() % BLOCK_HEADER_SIZE,
+            expected: BLOCK_HEADER_SIZE,
+        });
+    }
+    let mut decoded = Vec::with_capacity(headers.len() / BLOCK_HEADER_SIZE);
+    for header in headers.chunks(BLOCK_HEADER_SIZE) {
+        // Chunks are always the right size, so this can't fail.
+        decoded.push(BlockHeader::from_bytes(header).unwrap());
+    }
+    Ok(decoded)
+}
+
+// Verifies header values against the given tip. If successful, advances the tip
+// to the last header and returns the records of the verified headers by height.
+// The first skipped headers are already part of the chain and aren't verified again.
+fn verify_headers<S: Storage>(
+    storage: &S,
+    state: &State,
+    env: &Env,
+    tip: &mut ChainTip,
+    headers: &[BlockHeader],
+    skipped: usize,
+) -> Result<Vec<(u32, HeaderRecord)>, ContractError> {
+    check_max_update_length(state, headers.len())?;
+    check_min_update_length(state, headers.len() - skipped)?;
+    let mut records = Vec::with_capacity(headers.len() - skipped);
+    for (index, block_header) in headers.iter().enumerate().skip(skipped) {
+        records.push(verify_header(
+            storage,
+            state,
+            env,
+            tip,
+            index,
+            block_header,
+        )?);
+    }
+    Ok(records)
+}
+
+// The records of the valid headers by height, and the error that stopped verification.
+type ValidPrefix = (Vec<(u32, HeaderRecord)>, Option<ContractError>);
+
+// Verifies headers until the first invalid one. Advances the tip to the last valid
+// header and returns the records of the valid headers along with the error that
+// stopped verification, if any.
+fn verify_valid_prefix<S: Storage>(
+    storage: &S,
+    state: &State,
+    env: &Env,
+    tip: &mut ChainTip,
+    headers: &[BlockHeader],
+    skipped: usize,
+) -> Result<ValidPrefix, ContractError> {
+    check_max_update_length(state, headers.len())?;
+    let mut records = Vec::with_capacity(headers.len() - skipped);
+    for (index, block_header) in headers.iter().enumerate().skip(skipped) {
+        match verify_header(storage, state, env, tip, index, block_header) {
+            Ok(record) => records.push(record),
+            // Errors that aren't about the header itself aren't worth committing around.
+            Err(err @ ContractError::Std { .. }) => return Err(err),
+            Err(err) => {
+                // The prefix still has to be long enough. If it isn't, the header
+                // that stopped verification is the more useful error.
+                if check_min_update_length(state, records.len()).is_err() {
+                    return Err(err);
+                }
+                return Ok((records, Some(err)));
+            }
+        }
+    }
+    check_min_update_length(state, records.len())?;
+    Ok((records, None))
+}
+
+// Counts the leading headers that are already part of the current chain, which
+// happens when relayers race each other with overlapping batches. If there are new
+// headers after them, the known ones have to end at the tip, and otherwise none are
+// skipped so that verification reports where the batch stops following the chain.
+fn count_known_headers<S: Storage>(
+    storage: &S,
+    state: &State,
+    headers: &[BlockHeader],
+) -> StdResult<usize> {
+    let mut prev_height = None;
+    let mut known = headers.len();
+    for (index, header) in headers.iter().enumerate() {
+        let hash = header.hash().to_internal_hex();
+        let height = match header_heights_read(storage).may_load(hash.as_bytes())? {
+            Some(height) => height,
+            None => {
+                known = index;
+                break;
+            }
+        };
+        // The index can be left over from a branch that was replaced, so the
+        // header stored at the height has to match too.
+        match stored_header(storage, state, height)? {
+            Some(record) if record.hash == hash => {}
+            _ => {
+                known = index;
+                break;
+            }
+        }
+        if let Some(prev_height) = prev_height {
+            if height != prev_height + 1 {
+                known = index;
+                break;
+            }
+        }
+        prev_height = Some(height);
+    }
+    let tip_height = state.start_height + state.curr_offset;
+    if known < headers.len() && prev_height.is_some() && prev_height != Some(tip_height) {
+        return Ok(0);
+    }
+    Ok(known)
+}
+
+// Check that the number of block headers passed in is large enough.
+fn check_min_update_length(state: &State, num_headers: usize) -> Result<(), ContractError> {
+    let num_headers = u32::try_from(num_headers).unwrap();
+    if state.min_update_length > num_headers {
+        return Err(ContractError::TooFewHeaders {
+            provided: num_headers,
+            required: state.min_update_length,
+        });
+    }
+    Ok(())
+}
+
+// Check that the number of block headers passed in fits in a single update.
+fn check_max_update_length(state: &State, num_headers: usize) -> Result<(), ContractError> {
+    let num_headers = u32::try_from(num_headers).unwrap();
+    if state.max_update_length > 0 && num_headers > state.max_update_length {
+        return Err(ContractError::TooManyHeaders {
+            provided: num_headers,
+            allowed: state.max_update_length,
+        });
+    }
+    Ok(())
+}
+
+// Verifies a single header on top of the tip. If successful, advances the tip to
+// it and returns its record along with its height.
+fn verify_header<S: Storage>(
+    storage: &S,
+    state: &State,
+    env: &Env,
+    tip: &mut ChainTip,
+    index: usize,
+    block_header: &BlockHeader,
+) -> Result<(u32, HeaderRecord), ContractError> {
+    let thresh_diff = parse_threshold_difficulty(&state.threshold_difficulty)?;
+    let params = state.network.params();
+    let height = tip.height + 1;
+
+    // Check the difficulty bits in the header against the
+    // difficulty threshold stored by the contract.
+    let bits = block_header.bits;
+    let block_diff = bits_to_difficulty(bits);
+    if block_diff > thresh_diff {
+        return Err(ContractError::DifficultyAboveThreshold {
+            index,
+            difficulty: format!("{:x}", block_diff),
+            threshold: format!("{:x}", thresh_diff),
+        });
+    }
+
+    // Check the difficulty bits against the value expected by the retargeting rules.
+    let time = block_header.time;
+    let expected_bits = expected_bits(&params, tip, height, time);
+    if bits != expected_bits {
+        return Err(ContractError::UnexpectedBits {
+            index,
+            height,
+            bits,
+            expected: expected_bits,
+        });
+    }
+
+    // The timestamp must be greater than the median of the previous timestamps,
+    // and can't be too far ahead of the current time.
+    let median_time = median_time_past(&tip.recent_times);
+    if time <= median_time {
+        return Err(ContractError::TimestampTooEarly {
+            index,
+            height,
+            time,
+            median_time,
+        });
+    }
+    if u64::from(time) > env.block.time + MAX_FUTURE_BLOCK_TIME {
+        return Err(ContractError::TimestampTooLate {
+            index,
+            height,
+            time,
+            max_drift: MAX_FUTURE_BLOCK_TIME,
+            current_time: env.block.time,
+        });
+    }
+
+    // Check that the header references the correct previous header hash.
+    let prev_hash = block_header.prev_hash.to_internal_hex();
+    if prev_hash != tip.hash {
+        return Err(ContractError::PrevHashMismatch {
+            index,
+            expected: tip.hash.clone(),
+            actual: prev_hash,
+        });
+    }
+
+    // Check the difficulty of the header hash against the block difficulty.
+    let hash = block_header.hash();
+    let target = hash.to_u256();
+    if target > block_diff {
+        return Err(ContractError::InsufficientWork {
+            index,
+            hash: format!("{:x}", target),
+            difficulty: format!("{:x}", block_diff),
+        });
+    }
+
+    // Headers at checkpointed heights must have the checkpointed hash.
+    if let Some(checkpoint) = checkpoint_at(storage, height)? {
+        if checkpoint != hash.to_internal_hex() {
+            return Err(ContractError::CheckpointMismatch {
+                index,
+                height,
+                expected: checkpoint,
+                actual: hash.to_internal_hex(),
+            });
+        }
+    }
+
+    if height % params.retarget_interval == 0 {
+        tip.epoch_start_time = time;
+        tip.epoch_bits = bits;
+    }
+    tip.chainwork += block_work(bits);
+    tip.height = height;
+    tip.hash = hash.to_internal_hex();
+    tip.bits = bits;
+    tip.time = time;
+    tip.max_time = tip.max_time.max(time);
+    push_timestamp(&mut tip.recent_times, time);
+    Ok((
+        height,
+        HeaderRecord {
+            hash: tip.hash.clone(),
+            time,
+            bits,
+            merkle_root: Some(block_header.merkle_root.to_internal_hex()),
+            chainwork: format!("{:x}", tip.chainwork),
+            median_time_past: Some(median_time_past(&tip.recent_times)),
+            max_time: Some(tip.max_time),
+        },
+    ))
+}
+
+// The bits that the block header after the tip must declare, see GetNextWorkRequired
+// in Bitcoin Core. Bits only change on the first block of a difficulty epoch, unless
+// the network allows minimum difficulty blocks.
+fn expected_bits(params: &NetworkParams, tip: &ChainTip, height: u32, time: u32) -> u32 {
+    if height % params.retarget_interval == 0 {
+        if params.no_retargeting {
+            return tip.bits;
+        }
+        return retarget_bits(params, tip.bits, tip.epoch_start_time, tip.time);
+    }
+    if params.allow_min_difficulty_blocks {
+        // A block more than twice the target spacing after the previous one may
+        // use the pow limit. The blocks after it go back to the epoch's bits.
+        if u64::from(time) > u64::from(tip.time) + 2 * u64::from(params.target_spacing) {
+            return params.pow_limit_bits;
+        }
+        return tip.epoch_bits;
+    }
+    tip.bits
+}
+
+// Verifies header values. If successful, updates the offset
+// and the current block header hash. With accept_prefix, the valid headers before
+// the first invalid one are accepted.
+pub fn try_update_offset<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    headers: Vec<BlockHeader>,
+    accept_prefix: bool,
+    skip_callbacks: bool,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    if state.paused {
+        return Err(ContractError::Paused.into());
+    }
+
+    // Headers that were already accepted are skipped, and the first new header
+    // must reference the current hash stored by the contract.
+    let skipped = count_known_headers(&deps.storage, &state, &headers)?;
+    if skipped > 0 && skipped == headers.len() {
+        let mut logs = vec![
+            log("action", "update_block_offset"),
+            log("submitter", &env.message.sender),
+            log("headers_skipped", skipped),
+            log("headers_accepted", 0),
+        ];
+        logs.extend(tip_logs(&state));
+        return Ok(HandleResponse {
+            messages: vec![],
+            log: logs,
+            data: Some(to_binary(&update_result(&state, skipped, 0, None))?),
+        });
+    }
+    let mut tip = current_tip(&deps.storage, &state)?;
+    let (records, rejected) = if accept_prefix {
+        verify_valid_prefix(&deps.storage, &state, &env, &mut tip, &headers, skipped)?
+    } else {
+        (
+            verify_headers(&deps.storage, &state, &env, &mut tip, &headers, skipped)?,
+            None,
+        )
+    };
+    if !records.is_empty() {
+        state.last_update_block_height = env.block.height;
+        state.last_update_block_time = env.block.time;
+    }
+    let before = callback_clock(&deps.storage, &state)?;
+    save_tip(&mut deps.storage, &mut state, tip, &records)?;
+
+    let mut logs = vec![
+        log("action", "update_block_offset"),
+        log("submitter", &env.message.sender),
+        log("headers_skipped", skipped),
+        log("headers_accepted", records.len()),
+    ];
+    logs.extend(tip_logs(&state));
+    let rejected = rejected.map(|err| RejectedHeader {
+        index: (skipped + records.len()) as u32,
+        code: err.code().to_string(),
+        reason: err.to_string(),
+    });
+    if let Some(rejected) = &rejected {
+        logs.push(log("rejected_index", rejected.index));
+        logs.push(log("rejected_code", &rejected.code));
+        logs.push(log("rejected_reason", &rejected.reason));
+    }
+    let result = update_result(&state, skipped, records.len(), rejected);
+
+    let mut messages = pay_reward(deps, &env, records.len())?;
+    if !skip_callbacks {
+        let callbacks = execute_newly_due_callbacks(&mut deps.storage, &state, before)?;
+        logs.push(log("callbacks_executed", callbacks.len()));
+        messages.extend(callbacks);
+    }
+    Ok(HandleResponse {
+        messages,
+        log: logs,
+        data: Some(to_binary(&result)?),
+    })
+}
+
+// Verifies a competing branch of block headers that starts after a stored header.
+// If the branch has more cumulative work than the current chain, it becomes the
+// current chain.
+pub fn try_submit_fork<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    fork_point_height: u32,
+    headers: Vec<BlockHeader>,
+    skip_callbacks: bool,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    if state.paused {
+        return Err(ContractError::Paused.into());
+    }
+    let curr_tip = current_tip(&deps.storage, &state)?;
+
+    // The first header must reference the stored header at the fork point.
+    let mut tip = historical_tip(&deps.storage, &state, fork_point_height)?;
+    let records = verify_headers(&deps.storage, &state, &env, &mut tip, &headers, 0)?;
+    if tip.chainwork <= curr_tip.chainwork {
+        return Err(ContractError::InsufficientForkWork {
+            fork_chainwork: format!("{:x}", tip.chainwork),
+            chainwork: format!("{:x}", curr_tip.chainwork),
+        }
+        .into());
+    }
+    if !records.is_empty() {
+        state.last_update_block_height = env.block.height;
+        state.last_update_block_time = env.block.time;
+    }
+    let before = callback_clock(&deps.storage, &state)?;
+    save_tip(&mut deps.storage, &mut state, tip, &records)?;
+
+    let mut logs = vec![
+        log("action", "submit_fork"),
+        log("submitter", &env.message.sender),
+        log("fork_point_height", fork_point_height),
+        log("headers_accepted", records.len()),
+    ];
+    logs.extend(tip_logs(&state));
+    let mut messages = vec![];
+    if !skip_callbacks {
+        messages = execute_newly_due_callbacks(&mut deps.storage, &state, before)?;
+        logs.push(log("callbacks_executed", messages.len()));
+    }
+    Ok(HandleResponse {
+        messages,
+        log: logs,
+        data: Some(to_binary(&update_result(&state, 0, records.len(), None))?),
+    })
+}
+
+// Log attributes describing the tip after a message that set it.
+fn tip_logs(state: &State) -> Vec<LogAttribute> {
+    vec![
+        log("new_tip_height", state.start_height + state.curr_offset),
+        log("new_tip_hash", &state.curr_hash),
+        log("new_tip_time", state.curr_time),
+    ]
+}
+
+fn update_result(
+    state: &State,
+    skipped: usize,
+    accepted: usize,
+    rejected: Option<RejectedHeader>,
+) -> UpdateResult {
+    UpdateResult {
+        headers_skipped: skipped as u32,
+        headers_accepted: accepted as u32,
+        tip_height: state.start_height + state.curr_offset,
+        tip_hash: state.curr_hash.clone(),
+        tip_time: state.curr_time,
+        rejected,
+    }
+}
+
+// The tip of the chain currently followed by the contract.
+fn current_tip<S: Storage>(storage: &S, state: &State) -> StdResult<ChainTip> {
+    let height = state.start_height + state.curr_offset;
+    let recent_times = timestamps_read(storage).load()?;
+    let record = stored_header(storage, state, height)?;
+    Ok(ChainTip {
+        height,
+        hash: state.curr_hash.clone(),
+        bits: state.curr_bits,
+        time: state.curr_time,
+        epoch_start_time: state.epoch_start_time,
+        epoch_bits: state.epoch_bits,
+        max_time: max_time(record.as_ref(), &recent_times),
+        recent_times,
+        chainwork: parse_chainwork(&state.chainwork)?,
+    })
+}
+
+// The greatest timestamp up to a block. Records stored before version 5 don't
+// have it, so the greatest of the recent timestamps stands in for it.
+fn max_time(record: Option<&HeaderRecord>, recent_times: &[u32]) -> u32 {
+    match record.and_then(|record| record.max_time) {
+        Some(time) => time,
+        None => recent_times.iter().max().copied().unwrap_or(0),
+    }
+}
+
+// Rebuilds the tip at a block header in the stored header history.
+fn historical_tip<S: Storage>(storage: &S, state: &State, height: u32) -> StdResult<ChainTip> {
+    let record = match stored_header(storage, state, height)? {
+        Some(record) => record,
+        None => {
+            return Err(StdError::GenericErr {
+                msg: format!(
+                    "Fork point at height {} is not in the stored header history",
+                    height
+                ),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+    };
+
+    // Use whatever timestamps are stored for the headers leading up to the fork point.
+    let mut recent_times = Vec::with_capacity(MEDIAN_TIME_SPAN);
+    let first_height = height.saturating_sub(MEDIAN_TIME_SPAN as u32 - 1);
+    for prev_height in first_height..height {
+        if let Some(prev) = stored_header(storage, state, prev_height)? {
+            recent_times.push(prev.time);
+        }
+    }
+    recent_times.push(record.time);
+
+    // The first header of the epoch is only stored if it was accepted after the
+    // start block. Otherwise it has to be the epoch of the current tip.
+    let retarget_interval = state.network.params().retarget_interval;
+    let epoch_start_height = height - height % retarget_interval;
+    let tip_height = state.start_height + state.curr_offset;
+    let (epoch_start_time, epoch_bits) = match stored_header(storage, state, epoch_start_height)? {
+        Some(epoch_start) => (epoch_start.time, epoch_start.bits),
+        None if tip_height - tip_height % retarget_interval == epoch_start_height => {
+            (state.epoch_start_time, state.epoch_bits)
+        }
+        None => {
+            return Err(StdError::GenericErr {
+                msg: format!(
+                    "Start of the difficulty epoch at height {} is not in the stored header history",
+                    epoch_start_height
+                ),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+    };
+
+    Ok(ChainTip {
+        height,
+        max_time: max_time(Some(&record), &recent_times),
+        hash: record.hash,
+        bits: record.bits,
+        time: record.time,
+        epoch_start_time,
+        epoch_bits,
+        recent_times,
+        chainwork: parse_chainwork(&record.chainwork)?,
+    })
+}
+
+// Makes the tip the contract's current block and stores the records of the
+// headers that lead up to it.
+fn save_tip<S: Storage>(
+    storage: &mut S,
+    state: &mut State,
+    tip: ChainTip,
+    records: &[(u32, HeaderRecord)],
+) -> StdResult<()> {
+    state.curr_hash = tip.hash;
+    state.curr_offset = tip.height - state.start_height;
+    state.curr_bits = tip.bits;
+    state.curr_time = tip.time;
+    state.epoch_start_time = tip.epoch_start_time;
+    state.epoch_bits = tip.epoch_bits;
+    state.chainwork = format!("{:x}", tip.chainwork);
+    timestamps(storage).save(&tip.recent_times)?;
+    for (height, record) in records.iter() {
+        store_header(storage, *height, record)?;
+    }
+    state.oldest_height = prune_headers(
+        storage,
+        state.oldest_height,
+        tip.height,
+        state.header_retention,
+        records.len() + MAX_PRUNED_HEADERS,
+    )?;
+    config(storage).save(state)
+}
+
+// Loads the stored header record at a height. Records outside of the retained
+// range may be left over from before a reset or a fork, so they are ignored.
+fn stored_header<S: Storage>(
+    storage: &S,
+    state: &State,
+    height: u32,
+) -> StdResult<Option<HeaderRecord>> {
+    let tip_height = state.start_height + state.curr_offset;
+    if height < state.oldest_height || height > tip_height {
+        return Ok(None);
+    }
+    headers_read(storage).may_load(&height_key(height))
+}
+
+fn parse_threshold_difficulty(threshold: &str) -> StdResult<U256> {
+    match U256::from_str_radix(threshold, 16) {
+        Ok(res) => Ok(res),
+        Err(err) => Err(StdError::GenericErr {
+            msg: format!("Could not convert difficulty bits into U256: {}", err),
+            backtrace: Option::Some(Backtrace::generate()),
+        }),
+    }
+}
+
+fn parse_chainwork(chainwork: &str) -> StdResult<U256> {
+    match U256::from_str_radix(chainwork, 16) {
+        Ok(res) => Ok(res),
+        Err(err) => Err(StdError::GenericErr {
+            msg: format!(
+                "Could not convert chainwork \"{}\" into U256: {}",
+                chainwork, err
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        }),
+    }
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetContractInfo {} => to_binary(&query_info(deps)?),
+        QueryMsg::GetCurrentTime { confirmations } => {
+            to_binary(&query_current_time(deps, confirmations)?)
+        }
+        QueryMsg::GetFinalizedTip {} => to_binary(&query_finalized_tip(deps)?),
+        QueryMsg::GetStatus { block_time } => to_binary(&query_status(deps, block_time)?),
+        QueryMsg::GetHeader { height } => to_binary(&query_header(deps, height)?),
+        QueryMsg::GetHeaderByHash { hash } => to_binary(&query_header_by_hash(deps, hash)?),
+        QueryMsg::HeightAtTime {
+            timestamp,
+            use_mtp,
+            confirmations,
+        } => to_binary(&query_height_at_time(
+            deps,
+            timestamp,
+            use_mtp.unwrap_or(false),
+            confirmations,
+        )?),
+        QueryMsg::TimeAtHeight {
+            height,
+            confirmations,
+        } => to_binary(&query_time_at_height(deps, height, confirmations)?),
+        QueryMsg::VerifyTxInclusion {
+            height,
+            txid,
+            merkle_branch,
+            index,
+        } => to_binary(&query_verify_tx_inclusion(
+            deps,
+            height,
+            txid,
+            merkle_branch,
+            index,
+        )?),
+        QueryMsg::GetAdmins {} => to_binary(&query_admins(deps)?),
+        QueryMsg::GetRewardPool {} => to_binary(&query_reward_pool(deps)?),
+        QueryMsg::GetRelayerRewards { relayer } => {
+            to_binary(&query_relayer_rewards(deps, relayer)?)
+        }
+        QueryMsg::GetVersion {} => to_binary(&query_version(deps)?),
+        QueryMsg::GetCallbacks { start_after, limit } => {
+            to_binary(&query_callbacks(deps, start_after, limit)?)
+        }
+        QueryMsg::GetCheckpoints { start_after, limit } => {
+            to_binary(&query_checkpoints(deps, start_after, limit)?)
+        }
+    }
+}
+
+fn query_info<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<InfoResponse> {
+    let state = config_read(&deps.storage).load()?;
+    Ok(InfoResponse {
+        start_height: state.start_height,
+        min_difficulty: state.threshold_difficulty,
+        curr_hash: state.curr_hash,
+        curr_offset: state.curr_offset,
+        min_update_length: state.min_update_length,
+        max_update_length: state.max_update_length,
+        finality_depth: state.finality_depth,
+        chainwork: state.chainwork,
+        paused: state.paused,
+        network: state.network,
+    })
+}
+
+fn query_current_time<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    confirmations: Option<u32>,
+) -> StdResult<CurrentTimeResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let height = confirmed_height(&state, confirmations)?;
+    let (time, median_time) = if height == state.start_height + state.curr_offset {
+        let recent_times = timestamps_read(&deps.storage).load()?;
+        (state.curr_time, median_time_past(&recent_times))
+    } else {
+        let record = lookup_header(&deps.storage, &state, height)?;
+        (record.time, lookup_time(&record, height, true)?)
+    };
+    Ok(CurrentTimeResponse {
+        tip_height: height,
+        tip_time: time,
+        median_time_past: median_time,
+        lower_bound_time: median_time.saturating_sub(MAX_FUTURE_BLOCK_TIME as u32),
+        paused: state.paused,
+    })
+}
+
+fn query_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    block_time: Option<u64>,
+) -> StdResult<StatusResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let block_time = block_time.unwrap_or(state.last_update_block_time);
+    // Block timestamps can be ahead of the Secret Network block time, in which
+    // case there is no lag.
+    let lag = block_time.saturating_sub(u64::from(state.curr_time));
+    Ok(StatusResponse {
+        tip_height: state.start_height + state.curr_offset,
+        tip_time: state.curr_time,
+        last_update_block_height: state.last_update_block_height,
+        last_update_block_time: state.last_update_block_time,
+        block_time,
+        lag,
+        stale_threshold: state.stale_threshold,
+        stale: lag > state.stale_threshold,
+        paused: state.paused,
+    })
+}
+
+fn query_header<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    height: u32,
+) -> StdResult<HeaderResponse> {
+    let state = config_read(&deps.storage).load()?;
+    match stored_header(&deps.storage, &state, height)? {
+        Some(record) => Ok(HeaderResponse {
+            height,
+            hash: record.hash,
+            time: record.time,
+            bits: record.bits,
+            merkle_root: record.merkle_root,
+            chainwork: record.chainwork,
+            paused: state.paused,
+        }),
+        None => Err(StdError::NotFound {
+            kind: format!("Block header at height {}", height),
+            backtrace: None,
+        }),
+    }
+}
+
+fn query_header_by_hash<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    hash: String,
+) -> StdResult<HeaderResponse> {
+    let hash = hash.to_lowercase();
+    let height = header_heights_read(&deps.storage).may_load(hash.as_bytes())?;
+    if let Some(height) = height {
+        if let Ok(header) = query_header(deps, height) {
+            if header.hash == hash {
+                return Ok(header);
+            }
+        }
+    }
+    Err(StdError::NotFound {
+        kind: format!("Block header with hash {}", hash),
+        backtrace: None,
+    })
+}
+
+// Finds the first block header that reached the time with a binary search over the
+// stored header history. Block timestamps can decrease along the chain, so the
+// search uses the greatest timestamp up to each block instead, which first reaches
+// the time at the same block.
+fn query_height_at_time<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    timestamp: u32,
+    use_mtp: bool,
+    confirmations: Option<u32>,
+) -> StdResult<BlockTimeResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let kind = if use_mtp {
+        "median time past"
+    } else {
+        "timestamp"
+    };
+
+    let mut high = confirmed_height(&state, confirmations)?;
+    let tip = lookup_header(&deps.storage, &state, high)?;
+    if lookup_time(&tip, high, use_mtp)? < timestamp {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "No block header up to {} has a {} of at least {}",
+                describe_confirmed_height(&state, high),
+                kind,
+                timestamp
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    // Blocks before the oldest stored one may have reached the time already.
+    let mut low = state.oldest_height;
+    let oldest = lookup_header(&deps.storage, &state, low)?;
+    if lookup_time(&oldest, low, use_mtp)? >= timestamp {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "The first block header with a {} of at least {} is before the stored header history, which starts at height {}",
+                kind, timestamp, low
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    // The block at low hasn't reached the time and the block at high has.
+    let mut record = tip;
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        let mid_record = lookup_header(&deps.storage, &state, mid)?;
+        if lookup_time(&mid_record, mid, use_mtp)? >= timestamp {
+            high = mid;
+            record = mid_record;
+        } else {
+            low = mid;
+        }
+    }
+    Ok(block_time_response(&state, high, record))
+}
+
+fn query_time_at_height<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    height: u32,
+    confirmations: Option<u32>,
+) -> StdResult<BlockTimeResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let confirmed = confirmed_height(&state, confirmations)?;
+    if height > confirmed {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Height {} is beyond {}",
+                height,
+                describe_confirmed_height(&state, confirmed)
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    match stored_header(&deps.storage, &state, height)? {
+        Some(record) => Ok(block_time_response(&state, height, record)),
+        None => Err(StdError::GenericErr {
+            msg: format!(
+                "Block header at height {} is not in the stored header history, which starts at height {}",
+                height,
+                state.oldest_height
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        }),
+    }
+}
+
+fn query_finalized_tip<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<BlockTimeResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let height = confirmed_height(&state, Some(state.finality_depth.saturating_add(1)))?;
+    let record = lookup_header(&deps.storage, &state, height)?;
+    Ok(block_time_response(&state, height, record))
+}
+
+// Height of the latest stored block header with at least the given number of
+// confirmations, counting the block itself. No confirmations means the tip.
+fn confirmed_height(state: &State, confirmations: Option<u32>) -> StdResult<u32> {
+    let tip_height = state.start_height + state.curr_offset;
+    let depth = confirmations.unwrap_or(1).saturating_sub(1);
+    if depth > tip_height - state.oldest_height {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "No stored block header has {} confirmations with the tip at height {}",
+                depth + 1,
+                tip_height
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+    Ok(tip_height - depth)
+}
+
+// Describes a height returned by confirmed_height for error messages.
+fn describe_confirmed_height(state: &State, height: u32) -> String {
+    let tip_height = state.start_height + state.curr_offset;
+    if height == tip_height {
+        return format!("the tip at height {}", height);
+    }
+    format!(
+        "height {} with {} confirmations",
+        height,
+        tip_height - height + 1
+    )
+}
+
+fn block_time_response(state: &State, height: u32, record: HeaderRecord) -> BlockTimeResponse {
+    BlockTimeResponse {
+        height,
+        hash: record.hash,
+        time: record.time,
+        median_time_past: record.median_time_past,
+        paused: state.paused,
+    }
+}
+
+// Loads a stored block header that time lookups need.
+fn lookup_header<S: Storage>(storage: &S, state: &State, height: u32) -> StdResult<HeaderRecord> {
+    match stored_header(storage, state, height)? {
+        Some(record) => Ok(record),
+        None => Err(StdError::NotFound {
+            kind: format!("Block header at height {}", height),
+            backtrace: None,
+        }),
+    }
+}
+
+// The value that time lookups search by, which never decreases along the chain.
+fn lookup_time(record: &HeaderRecord, height: u32, use_mtp: bool) -> StdResult<u32> {
+    let time = if use_mtp {
+        record.median_time_past
+    } else {
+        record.max_time
+    };
+    match time {
+        Some(time) => Ok(time),
+        None => Err(StdError::GenericErr {
+            msg: format!(
+                "Block header at height {} was accepted before time lookups were supported",
+                height
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        }),
+    }
+}
+
+fn query_verify_tx_inclusion<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    height: u32,
+    txid: String,
+    merkle_branch: Vec<String>,
+    index: u32,
+) -> StdResult<TxInclusionResponse> {
+    let header = query_header(deps, height)?;
+    let merkle_root = match header.merkle_root {
+        Some(merkle_root) => merkle_root,
+        None => {
+            return Err(StdError::GenericErr {
+                msg: format!(
+                    "Merkle root of the block header at height {} is not known",
+                    height
+                ),
+                backtrace: Option::Some(Backtrace::generate()),
+            });
+        }
+    };
+
+    let txid = decode_hash(&txid)?;
+    let mut branch = Vec::with_capacity(merkle_branch.len());
+    for hash in merkle_branch.iter() {
+        branch.push(decode_hash(hash)?);
+    }
+    let computed: String = compute_merkle_root(txid, &branch, index)?.to_hex();
+    if computed != merkle_root {
+        return Err(StdError::GenericErr {
+            msg: format!(
+                "Computed merkle root {} does not match merkle root {} of the block header at height {}",
+                computed, merkle_root, height
+            ),
+            backtrace: Option::Some(Backtrace::generate()),
+        });
+    }
+
+    let state = config_read(&deps.storage).load()?;
+    Ok(TxInclusionResponse {
+        height,
+        block_hash: header.hash,
+        block_time: header.time,
+        confirmations: state.start_height + state.curr_offset - height + 1,
+        paused: state.paused,
+    })
+}
+
+fn query_admins<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<AdminsResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let pending_owner = match state.pending_owner {
+        Some(pending_owner) => Some(deps.api.human_address(&pending_owner)?),
+        None => None,
+    };
+    let mut admin_infos = vec![];
+    for admin in admins_read(&deps.storage).load()? {
+        admin_infos.push(AdminInfo {
+            address: deps.api.human_address(&admin.address)?,
+            permissions: admin.permissions,
+        });
+    }
+    Ok(AdminsResponse {
+        owner: deps.api.human_address(&state.owner)?,
+        pending_owner,
+        admins: admin_infos,
+    })
+}
+
+fn query_reward_pool<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<RewardPoolResponse> {
+    let pool = rewards_read(&deps.storage).load()?;
+    Ok(RewardPoolResponse {
+        denom: REWARD_DENOM.to_string(),
+        pool: pool.pool,
+        reward_per_header: pool.per_header,
+        max_reward_per_update: pool.max_per_update,
+        total_paid: pool.total_paid,
+    })
+}
+
+fn query_relayer_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    relayer: HumanAddr,
+) -> StdResult<RelayerRewardsResponse> {
+    let relayer_raw = deps.api.canonical_address(&relayer)?;
+    let rewards_paid = relayer_rewards_read(&deps.storage)
+        .may_load(relayer_raw.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    Ok(RelayerRewardsResponse {
+        relayer,
+        rewards_paid,
+    })
+}
+
+fn query_version<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<VersionResponse> {
+    Ok(VersionResponse {
+        version: version_read(&deps.storage).may_load()?.unwrap_or(0),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+fn query_callbacks<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<CallbacksResponse> {
+    let state = config_read(&deps.storage).load()?;
+    let tip_height = state.start_height + state.curr_offset;
+    let median_time = median_time_past(&timestamps_read(&deps.storage).load()?);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as usize;
+
+    let mut callbacks = vec![];
+    for id in pending_callbacks_read(&deps.storage).load()? {
+        if callbacks.len() >= limit {
+            break;
+        }
+        // Ids are increasing in registration order.
+        if let Some(start_after) = start_after {
+            if id <= start_after {
+                continue;
+            }
+        }
+        let callback = callbacks_read(&deps.storage).load(&callback_key(id))?;
+        callbacks.push(CallbackInfo {
+            id,
+            registrant: deps.api.human_address(&callback.registrant)?,
+            condition: callback.condition,
+            contract_addr: callback.contract_addr,
+            callback_code_hash: callback.callback_code_hash,
+            msg: callback.msg,
+            due: callback_due(&callback.condition, tip_height, median_time),
+        });
+    }
+    Ok(CallbacksResponse { callbacks })
+}
+
+fn query_checkpoints<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<u32>,
+    limit: Option<u32>,
+) -> StdResult<CheckpointsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as usize;
+    let mut checkpoints = vec![];
+    for height in checkpoint_heights_read(&deps.storage).load()? {
+        if checkpoints.len() >= limit {
+            break;
+        }
+        if let Some(start_after) = start_after {
+            if height <= start_after {
+                continue;
+            }
+        }
+        if let Some(hash) = checkpoint_at(&deps.storage, height)? {
+            checkpoints.push(Checkpoint { height, hash });
+        }
+    }
+    Ok(CheckpointsResponse { checkpoints })
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::history::headers;
+    use crate::state::{StateV0, CONFIG_KEY};
     use cosmwasm_std::testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage};
     use cosmwasm_std::{coins, from_binary, StdError};
+    use cosmwasm_storage::singleton;
     use std::io::Empty;
 
-    fn default_init_msg() -> InitMsg {
-        InitMsg {
-            start_height: 125551,
-            min_difficulty_bits: 0x1b0404cbu32,
-            start_hash: "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
-                .parse()
-                .unwrap(),
-            min_update_length: 3,
+    // Double hashes a hex string, like a block header, and returns a hex string.
+    fn double_hash_hex(hex_str: &str) -> String {
+        let inp: Vec<u8> = hex_str.from_hex().unwrap();
+        double_sha256(&inp).to_hex()
+    }
+
+    fn default_init_msg() -> InitMsg {
+        InitMsg {
+            start_height: 125551,
+            min_difficulty_bits: 0x1b0404cbu32,
+            start_hash: "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
+                .parse()
+                .unwrap(),
+            min_update_length: 3,
+            start_bits: 0x1a44b9f2u32,
+            start_time: 1305998000,
+            epoch_start_time: 1305000000,
+            prev_timestamps: vec![],
+            header_retention: 0,
+            start_chainwork: "0".to_string(),
+            network: None,
+            epoch_start_bits: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        }
+    }
+
+    fn test_block_headers() -> Vec<String> {
+        // Values are encoded as hex strings in little endian format.
+        vec![
+            [
+                // version
+                "01000000",
+                // previous block hash
+                "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
+                // merkle root hash
+                "e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122b",
+                // unix timestamp
+                "c7f5d74d",
+                // difficulty bits
+                "f2b9441a",
+                // nonce
+                "42a14695",
+            ]
+            .concat(),
+            [
+                "01000000",
+                "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000",
+                "b371c14921b20c2895ed76545c116e0ad70167c5c4952ca201f5d544a26efb53",
+                "b4f6d74d",
+                "f2b9441a",
+                "071a0c81",
+            ]
+            .concat(),
+            [
+                "01000000",
+                "85afcb448a3fcde31dc78babd352d9dbde6fcb566777ea33051c000000000000",
+                "ca5b6b96fe65e1a7d50e7c3025a176472ba26d44512de86a6f3e39649330cd2f",
+                "16f7d74d",
+                "f2b9441a",
+                "8574adaf",
+            ]
+            .concat(),
+        ]
+    }
+
+    // Builds a block header on top of prev_hash, searching for a nonce that
+    // satisfies the proof of work. Only practical for very easy bits.
+    fn mine_header(prev_hash: &str, merkle_root: &str, time: u32, bits: u32) -> String {
+        let target = bits_to_difficulty(bits);
+        let mut header = BlockHeader {
+            version: 1,
+            prev_hash: BlockHash::from_internal_hex(prev_hash).unwrap(),
+            merkle_root: BlockHash::from_internal_hex(merkle_root).unwrap(),
+            time,
+            bits,
+            nonce: 0,
+        };
+        while header.hash().to_u256() > target {
+            header.nonce += 1;
+        }
+        header.to_hex()
+    }
+
+    // Mines a chain of block headers on top of prev_hash, ten minutes apart.
+    fn mine_chain(prev_hash: &str, merkle_root: &str, start_time: u32, count: u32) -> Vec<String> {
+        let mut prev_hash = prev_hash.to_string();
+        let mut chain = vec![];
+        for i in 0..count {
+            let header = mine_header(
+                &prev_hash,
+                merkle_root,
+                start_time + 600 * (i + 1),
+                EASY_BITS,
+            );
+            prev_hash = double_hash_hex(&header);
+            chain.push(header);
+        }
+        chain
+    }
+
+    // Bits with a target easy enough to mine headers in tests.
+    const EASY_BITS: u32 = 0x207fffff;
+
+    fn easy_init_msg() -> InitMsg {
+        InitMsg {
+            start_height: 1000,
+            min_difficulty_bits: EASY_BITS,
+            start_hash: "00".repeat(32),
+            min_update_length: 2,
+            start_bits: EASY_BITS,
+            start_time: 1500000000,
+            epoch_start_time: 1500000000,
+            prev_timestamps: vec![],
+            header_retention: 0,
+            start_chainwork: "0".to_string(),
+            network: Some(Network::Regtest),
+            epoch_start_bits: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        }
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        // The start_hash is a little endian hex string.
+        let start_hash: String = "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
+            .parse()
+            .unwrap();
+        let min_bits = bits_to_difficulty(0x1b0404cbu32);
+
+        let env = mock_env("creator", &coins(1000, "earth"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = init(&mut deps, env, default_init_msg()).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(start_hash, value.curr_hash);
+        assert_eq!(0, value.curr_offset);
+        assert_eq!(125551, value.start_height);
+        assert_eq!(format!("{:x}", min_bits), value.min_difficulty);
+        assert_eq!(3, value.min_update_length);
+        assert_eq!("0", value.chainwork);
+    }
+
+    #[test]
+    fn double_hash_test() {
+        // See https://en.bitcoin.it/wiki/Block_hashing_algorithm.
+        let a = "01000000";
+        let b = "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000";
+        let c = "e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122b";
+        let d = "c7f5d74d";
+        let e = "f2b9441a";
+        let f = "42a14695";
+        let result: String = [a, b, c, d, e, f].join("");
+        let hashed = double_hash_hex(&result);
+        assert_eq!(
+            hashed,
+            "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000"
+        )
+    }
+
+    #[test]
+    fn retarget_bits_test() {
+        let params = Network::Mainnet.params();
+        // Cases from pow_tests.cpp in Bitcoin Core.
+        // Block #32255, the first retarget that changed the difficulty.
+        assert_eq!(
+            retarget_bits(&params, 0x1d00ffff, 1261130161, 1262152739),
+            0x1d00d86a
+        );
+        // Block #2015, clamped to the pow limit.
+        assert_eq!(
+            retarget_bits(&params, 0x1d00ffff, 1231006505, 1233061996),
+            0x1d00ffff
+        );
+        // Block #68543, clamped to a 4x difficulty increase.
+        assert_eq!(
+            retarget_bits(&params, 0x1c05a3f4, 1279008237, 1279297671),
+            0x1c0168fd
+        );
+        // Block #46367, clamped to a 4x difficulty decrease.
+        assert_eq!(
+            retarget_bits(&params, 0x1c387f6f, 1263163443, 1269211443),
+            0x1d00e1fd
+        );
+    }
+
+    #[test]
+    fn difficulty_to_bits_test() {
+        for bits in &[
+            0x1d00ffffu32,
+            0x1b0404cb,
+            0x1a44b9f2,
+            0x1c05a3f4,
+            0x207fffff,
+        ] {
+            assert_eq!(difficulty_to_bits(bits_to_difficulty(*bits)), *bits);
+        }
+    }
+
+    #[test]
+    fn median_time_past_test() {
+        assert_eq!(median_time_past(&[5]), 5);
+        assert_eq!(median_time_past(&[3, 1, 2]), 2);
+        assert_eq!(median_time_past(&[1, 2, 3, 4]), 3);
+        assert_eq!(median_time_past(&[9, 1, 8, 2, 7, 3, 6, 4, 5, 10, 11]), 6);
+
+        let mut recent_times = vec![];
+        for time in 0..20 {
+            push_timestamp(&mut recent_times, time);
+        }
+        assert_eq!(recent_times, (9..20).collect::<Vec<u32>>());
+        assert_eq!(median_time_past(&recent_times), 14);
+    }
+
+    // TODO: add more tests
+    /*
+    #[test]
+    fn bits_to_difficulty_test() {}
+     */
+
+    #[test]
+    fn update() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        // anyone can update
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // should increase offset by 3
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.curr_offset);
+        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
+        assert_eq!(
+            "1e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df1211000000000000",
+            value.curr_hash
+        );
+        // should add the work of 3 blocks
+        assert_eq!(format!("{:x}", block_work(0x1a44b9f2) * 3), value.chainwork);
+    }
+
+    #[test]
+    fn update_packed() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        let packed: Vec<u8> = test_block_headers().concat().from_hex().unwrap();
+
+        // a truncated header is rejected
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffsetPacked {
+            headers: Binary::from(&packed[..packed.len() - 1]),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "bad_header_length[2]: Encoded block header length is 79, must be 80"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffsetPacked {
+            headers: Binary::from(packed),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // the same as updating with hex headers
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.curr_offset);
+        assert_eq!(
+            "1e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df1211000000000000",
+            value.curr_hash
+        );
+        let res = query(&deps, QueryMsg::GetHeader { height: 125553 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            Some("b371c14921b20c2895ed76545c116e0ad70167c5c4952ca201f5d544a26efb53".to_string()),
+            value.merkle_root
+        );
+    }
+
+    #[test]
+    fn block_work_test() {
+        // The minimum difficulty target takes 2^32 + 2^16 + 1 hashes on average.
+        assert_eq!(block_work(0x1d00ffff), U256::from(0x100010001u64));
+        assert_eq!(block_work(0x207fffff), U256::from(2));
+        assert_eq!(block_work(0), U256::zero());
+    }
+
+    #[test]
+    fn current_time() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        // before any update the start block is the tip
+        let res = query(
+            &deps,
+            QueryMsg::GetCurrentTime {
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: CurrentTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(125551, value.tip_height);
+        assert_eq!(1305998000, value.tip_time);
+        assert_eq!(1305998000, value.median_time_past);
+        assert_eq!(1305998000 - 7200, value.lower_bound_time);
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::GetCurrentTime {
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: CurrentTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(125554, value.tip_height);
+        assert_eq!(1305999126, value.tip_time);
+        assert_eq!(1305999028, value.median_time_past);
+        assert_eq!(1305999028 - 7200, value.lower_bound_time);
+    }
+
+    #[test]
+    fn header_history() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // the start block is stored without a merkle root
+        let res = query(&deps, QueryMsg::GetHeader { height: 125551 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
+            value.hash
+        );
+        assert_eq!(None, value.merkle_root);
+
+        let res = query(&deps, QueryMsg::GetHeader { height: 125553 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            HeaderResponse {
+                height: 125553,
+                hash: "85afcb448a3fcde31dc78babd352d9dbde6fcb566777ea33051c000000000000"
+                    .to_string(),
+                time: 1305999028,
+                bits: 0x1a44b9f2,
+                merkle_root: Some(
+                    "b371c14921b20c2895ed76545c116e0ad70167c5c4952ca201f5d544a26efb53".to_string()
+                ),
+                chainwork: format!("{:x}", block_work(0x1a44b9f2) * 2),
+                paused: false,
+            },
+            value
+        );
+
+        let res = query(
+            &deps,
+            QueryMsg::GetHeaderByHash {
+                hash: "85AFCB448A3FCDE31DC78BABD352D9DBDE6FCB566777EA33051C000000000000"
+                    .to_string(),
+            },
+        )
+        .unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!(125553, value.height);
+
+        // heights past the tip aren't stored
+        let res = query(&deps, QueryMsg::GetHeader { height: 125555 });
+        match res {
+            Err(StdError::NotFound { kind, .. }) => {
+                assert_eq!(kind, "Block header at height 125555");
+            }
+            _ => panic!("Must return not found"),
+        }
+    }
+
+    #[test]
+    fn header_retention() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let mut msg = default_init_msg();
+        msg.header_retention = 2;
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // only the last two headers are kept
+        for height in 125551..125553 {
+            assert!(query(&deps, QueryMsg::GetHeader { height }).is_err());
+        }
+        for height in 125553..125555 {
+            assert!(query(&deps, QueryMsg::GetHeader { height }).is_ok());
+        }
+        let res = query(
+            &deps,
+            QueryMsg::GetHeaderByHash {
+                hash: "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000"
+                    .to_string(),
+            },
+        );
+        match res {
+            Err(StdError::NotFound { kind, .. }) => {
+                assert_eq!(kind, "Block header with hash 1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000");
+            }
+            _ => panic!("Must return not found"),
+        }
+        assert_eq!(
+            None,
+            headers_read(&deps.storage)
+                .may_load(&height_key(125552))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn lowered_retention_pruned() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 150);
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // lowering the retention removes a page of the records that fall out of it
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: Some(10),
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1100, state.oldest_height);
+        for height in &[1000, 1099] {
+            assert_eq!(
+                None,
+                headers_read(&deps.storage)
+                    .may_load(&height_key(*height))
+                    .unwrap()
+            );
+        }
+        assert!(headers_read(&deps.storage)
+            .may_load(&height_key(1100))
+            .unwrap()
+            .is_some());
+
+        // the following update removes the rest
+        let tip_hash = double_hash_hex(&chain[149]);
+        let chain = mine_chain(&tip_hash, &"aa".repeat(32), 1500090000, 2);
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1143, state.oldest_height);
+        for height in 1100..1143 {
+            assert_eq!(
+                None,
+                headers_read(&deps.storage)
+                    .may_load(&height_key(height))
+                    .unwrap()
+            );
+        }
+        for height in 1143..1153 {
+            assert!(query(&deps, QueryMsg::GetHeader { height }).is_ok());
+        }
+    }
+
+    #[test]
+    fn fork() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        let main_chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 3);
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: main_chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // a branch with the same amount of work doesn't replace the current chain
+        let fork_hash = double_hash_hex(&main_chain[0]);
+        let fork_chain = mine_chain(&fork_hash, &"bb".repeat(32), 1500000600, 3);
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::SubmitFork {
+            fork_point_height: 1001,
+            block_headers: fork_chain[..2].to_vec(),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        let work = block_work(EASY_BITS);
+        match res {
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(
+                    msg,
+                    format!(
+                        "insufficient_fork_work: Fork chainwork {:x} must be greater than current chainwork {:x}",
+                        work * 3,
+                        work * 3
+                    )
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // the fork point must be stored
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::SubmitFork {
+            fork_point_height: 1004,
+            block_headers: fork_chain.clone(),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(
+                    msg,
+                    "Fork point at height 1004 is not in the stored header history"
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // a heavier branch becomes the current chain
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::SubmitFork {
+            fork_point_height: 1001,
+            block_headers: fork_chain.clone(),
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(4, value.curr_offset);
+        assert_eq!(double_hash_hex(&fork_chain[2]), value.curr_hash);
+
+        let res = query(&deps, QueryMsg::GetHeader { height: 1002 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!(double_hash_hex(&fork_chain[0]), value.hash);
+        assert_eq!(format!("{:x}", work * 2), value.chainwork);
+
+        // headers from the old branch can no longer be found
+        let res = query(
+            &deps,
+            QueryMsg::GetHeaderByHash {
+                hash: double_hash_hex(&main_chain[1]),
+            },
+        );
+        assert!(res.is_err());
+
+        // the current chain can be extended from the new tip
+        let next = mine_chain(
+            &double_hash_hex(&fork_chain[2]),
+            &"bb".repeat(32),
+            1500002400,
+            2,
+        );
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: next,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let res = query(
+            &deps,
+            QueryMsg::GetCurrentTime {
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: CurrentTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(1006, value.tip_height);
+        assert_eq!(1500003600, value.tip_time);
+    }
+
+    #[test]
+    fn tx_inclusion() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // The transactions and merkle root of block 100000, in little endian.
+        let txids = [
+            "876dd0a3ef4a2816ffd1c12ab649825a958b0ff3bb3d6f3e1250f13ddbf0148c",
+            "c40297f730dd7b5a99567eb8d27b78758f607507c52292d02d4031895b52f2ff",
+            "c46e239ab7d28e2c019b6d66ad8fae98a56ef1f21aeecb94d1b1718186f05963",
+            "1d0cb83721529a062d9675b98d6e5c587e4a770fc84ed00abc5a5de04568a6e9",
+        ];
+        let merkle_root = "6657a9252aacd5c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f3";
+        let pair_0_1 = "15b88c5107195bf09eb9da89b83d95b3d070079a3c5c5d3d17d0dcd873fbdacc";
+
+        let mut headers = mine_chain(&"00".repeat(32), merkle_root, 1500000000, 1);
+        headers.extend(mine_chain(
+            &double_hash_hex(&headers[0]),
+            &"aa".repeat(32),
+            1500000600,
+            2,
+        ));
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: headers.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::VerifyTxInclusion {
+                height: 1001,
+                txid: txids[2].to_string(),
+                merkle_branch: vec![txids[3].to_string(), pair_0_1.to_string()],
+                index: 2,
+            },
+        )
+        .unwrap();
+        let value: TxInclusionResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            TxInclusionResponse {
+                height: 1001,
+                block_hash: double_hash_hex(&headers[0]),
+                block_time: 1500000600,
+                confirmations: 3,
+                paused: false,
+            },
+            value
+        );
+
+        // the wrong position doesn't match the merkle root
+        let res = query(
+            &deps,
+            QueryMsg::VerifyTxInclusion {
+                height: 1001,
+                txid: txids[2].to_string(),
+                merkle_branch: vec![txids[3].to_string(), pair_0_1.to_string()],
+                index: 3,
+            },
+        );
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert!(msg.starts_with("Computed merkle root"))
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // the index must fit in the branch
+        let res = query(
+            &deps,
+            QueryMsg::VerifyTxInclusion {
+                height: 1001,
+                txid: txids[2].to_string(),
+                merkle_branch: vec![txids[3].to_string(), pair_0_1.to_string()],
+                index: 4,
+            },
+        );
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Transaction index 4 is out of range for a merkle branch of 2 hashes"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        // the start block's merkle root isn't known
+        let res = query(
+            &deps,
+            QueryMsg::VerifyTxInclusion {
+                height: 1000,
+                txid: txids[2].to_string(),
+                merkle_branch: vec![],
+                index: 0,
+            },
+        );
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Merkle root of the block header at height 1000 is not known"
+            ),
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn tx_inclusion_64_byte_transaction() {
+        // A 64 byte transaction with one input and one output.
+        let tx: Vec<u8> = [
+            // version
+            "01000000",
+            // one input spending a previous output
+            "01",
+            &"ab".repeat(36),
+            // empty signature script and sequence
+            "00",
+            "ffffffff",
+            // one output with a value and a 4 byte script
+            "01",
+            "0000000000000000",
+            "04",
+            "6a026a6a",
+            // lock time
+            "00000000",
+        ]
+        .concat()
+        .from_hex()
+        .unwrap();
+        let mut tx_bytes = [0u8; 64];
+        tx_bytes.copy_from_slice(&tx);
+        assert!(could_be_transaction(&tx_bytes));
+
+        let other = [0x42u8; 32];
+        let merkle_root = double_sha256(&[double_sha256(&tx), other].concat());
+
+        // The real transaction can be proven.
+        let root = compute_merkle_root(double_sha256(&tx), &[other], 0).unwrap();
+        assert_eq!(root, merkle_root);
+
+        // The halves of the transaction pretend to be a transaction and its sibling.
+        let mut first_half = [0u8; 32];
+        first_half.copy_from_slice(&tx[..32]);
+        let mut second_half = [0u8; 32];
+        second_half.copy_from_slice(&tx[32..]);
+        let res = compute_merkle_root(first_half, &[second_half, other], 0);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Merkle branch is ambiguous, the transaction and its sibling could be a 64 byte transaction"
+            ),
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        // anyone can update
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // should increase offset by 3
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.curr_offset);
+        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
+        assert_eq!(
+            "1e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df1211000000000000",
+            value.curr_hash
+        );
+
+        // not anyone can reset
+        let unauth_env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::ResetState {
+            new_state: default_init_msg(),
+        };
+        let res = handle(&mut deps, unauth_env, msg);
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // only the original creator can reset the state
+        let auth_env = mock_env("creator", &coins(2, "token"));
+        let mut msg = default_init_msg();
+        msg.min_difficulty_bits = 0x11deadbfu32;
+        msg.min_update_length = 42;
+        msg.start_height = 42;
+        msg.start_chainwork =
+            "00000000000000000000000000000000000000000000000005ad7a1bb3b1c1d0".to_string();
+        let msg = HandleMsg::ResetState { new_state: msg };
+        let _res = handle(&mut deps, auth_env, msg).unwrap();
+
+        // should now be the original values
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.curr_offset);
+        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
+        assert_eq!(
+            "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
+            value.curr_hash
+        );
+        assert_eq!(42, value.min_update_length);
+        assert_eq!(42, value.start_height);
+        assert_eq!("5eadbf0000000000000000000000000000", value.min_difficulty);
+        assert_eq!("5ad7a1bb3b1c1d0", value.chainwork);
+    }
+
+    #[test]
+    fn ownership_transfer() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        // not anyone can transfer ownership
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::TransferOwnership {
+            new_owner: HumanAddr::from("anyone"),
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::TransferOwnership {
+            new_owner: HumanAddr::from("successor"),
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // the owner doesn't change until the transfer is accepted
+        let res = query(&deps, QueryMsg::GetAdmins {}).unwrap();
+        let value: AdminsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            AdminsResponse {
+                owner: HumanAddr::from("creator"),
+                pending_owner: Some(HumanAddr::from("successor")),
+                admins: vec![],
+            },
+            value
+        );
+
+        // only the pending owner can accept
+        let env = mock_env("anyone", &coins(2, "token"));
+        let res = handle(&mut deps, env, HandleMsg::AcceptOwnership {});
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let env = mock_env("successor", &coins(2, "token"));
+        let _res = handle(&mut deps, env, HandleMsg::AcceptOwnership {}).unwrap();
+
+        let res = query(&deps, QueryMsg::GetAdmins {}).unwrap();
+        let value: AdminsResponse = from_binary(&res).unwrap();
+        assert_eq!(HumanAddr::from("successor"), value.owner);
+        assert_eq!(None, value.pending_owner);
+
+        // the previous owner can't reset anymore, the new one can
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::ResetState {
+            new_state: default_init_msg(),
+        };
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("successor", &coins(2, "token"));
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // the transfer can't be accepted twice
+        let env = mock_env("successor", &coins(2, "token"));
+        let res = handle(&mut deps, env, HandleMsg::AcceptOwnership {});
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+    }
+
+    #[test]
+    fn admin_permissions() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        // only the owner can set admins
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("anyone"),
+            permissions: vec![Permission::Reset],
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("configurer"),
+            permissions: vec![Permission::Configure, Permission::Configure],
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("resetter"),
+            permissions: vec![Permission::Reset, Permission::Pause],
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetAdmins {}).unwrap();
+        let value: AdminsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            vec![
+                AdminInfo {
+                    address: HumanAddr::from("configurer"),
+                    permissions: vec![Permission::Configure],
+                },
+                AdminInfo {
+                    address: HumanAddr::from("resetter"),
+                    permissions: vec![Permission::Reset, Permission::Pause],
+                },
+            ],
+            value.admins
+        );
+
+        // an admin without the configure permission can't update the config
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: Some(5),
+            header_retention: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        let env = mock_env("resetter", &coins(2, "token"));
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("configurer", &coins(2, "token"));
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.min_update_length);
+        assert_eq!(
+            format!("{:x}", bits_to_difficulty(0x1b0404cbu32)),
+            value.min_difficulty
+        );
+
+        // an admin without the reset permission can't reset
+        let msg = HandleMsg::ResetState {
+            new_state: default_init_msg(),
+        };
+        let env = mock_env("configurer", &coins(2, "token"));
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("resetter", &coins(2, "token"));
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // removing all permissions removes the admin
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("configurer"),
+            permissions: vec![],
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let res = query(&deps, QueryMsg::GetAdmins {}).unwrap();
+        let value: AdminsResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.admins.len());
+        assert_eq!(HumanAddr::from("resetter"), value.admins[0].address);
+    }
+
+    #[test]
+    fn pause() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("guardian"),
+            permissions: vec![Permission::Pause],
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // not anyone can pause
+        let env = mock_env("anyone", &coins(2, "token"));
+        let res = handle(&mut deps, env, HandleMsg::Pause {});
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let env = mock_env("guardian", &coins(2, "token"));
+        let _res = handle(&mut deps, env, HandleMsg::Pause {}).unwrap();
+
+        // queries report that the contract is paused
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert!(value.paused);
+        let res = query(
+            &deps,
+            QueryMsg::GetCurrentTime {
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: CurrentTimeResponse = from_binary(&res).unwrap();
+        assert!(value.paused);
+        let res = query(
+            &deps,
+            QueryMsg::TimeAtHeight {
+                height: 125551,
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert!(value.paused);
+        let res = query(&deps, QueryMsg::GetHeader { height: 125551 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert!(value.paused);
+
+        // headers aren't accepted while paused
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "paused: Block header updates are paused")
+            }
+            _ => panic!("Must return an error"),
+        }
+        let env = mock_env("anyone", &coins(2, "token"));
+        let fork_msg = HandleMsg::SubmitFork {
+            fork_point_height: 125551,
+            block_headers: test_block_headers(),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, fork_msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "paused: Block header updates are paused")
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // the owner can unpause too
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = handle(&mut deps, env, HandleMsg::Unpause {}).unwrap();
+        let env = mock_env("anyone", &coins(2, "token"));
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert!(!value.paused);
+        assert_eq!(3, value.curr_offset);
+    }
+
+    #[test]
+    fn relayer_rewards_paid() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // only the reward denomination can be funded
+        let env = mock_env("funder", &coins(1000, "token"));
+        let res = handle(&mut deps, env, HandleMsg::Fund {});
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The reward pool can only be funded with uscrt, not token"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        let env = mock_env("funder", &coins(250, "uscrt"));
+        let _res = handle(&mut deps, env, HandleMsg::Fund {}).unwrap();
+
+        // only the owner can set rewards
+        let msg = HandleMsg::SetRewards {
+            reward_per_header: Uint128(40),
+            max_reward_per_update: Uint128(100),
+        };
+        let env = mock_env("anyone", &[]);
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("creator", &[]);
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // 3 headers would be 120, but an update pays at most 100
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 5);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[..3].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: env.contract.address.clone(),
+                to_address: HumanAddr::from("relayer"),
+                amount: coins(100, "uscrt"),
+            })],
+            res.messages
+        );
+
+        // 2 headers would be 80, but only 150 is left in the pool after a second update
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[3..].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: env.contract.address.clone(),
+                to_address: HumanAddr::from("relayer"),
+                amount: coins(80, "uscrt"),
+            })],
+            res.messages
+        );
+
+        let res = query(&deps, QueryMsg::GetRewardPool {}).unwrap();
+        let value: RewardPoolResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            RewardPoolResponse {
+                denom: "uscrt".to_string(),
+                pool: Uint128(70),
+                reward_per_header: Uint128(40),
+                max_reward_per_update: Uint128(100),
+                total_paid: Uint128(180),
+            },
+            value
+        );
+        let res = query(
+            &deps,
+            QueryMsg::GetRelayerRewards {
+                relayer: HumanAddr::from("relayer"),
+            },
+        )
+        .unwrap();
+        let value: RelayerRewardsResponse = from_binary(&res).unwrap();
+        assert_eq!(Uint128(180), value.rewards_paid);
+
+        // an empty pool stops paying
+        let more = mine_chain(&double_hash_hex(&chain[4]), &"aa".repeat(32), 1500003000, 4);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: more[..2].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: more[2..].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        let res = query(&deps, QueryMsg::GetRewardPool {}).unwrap();
+        let value: RewardPoolResponse = from_binary(&res).unwrap();
+        assert_eq!(Uint128(0), value.pool);
+        assert_eq!(Uint128(250), value.total_paid);
+    }
+
+    #[test]
+    fn callbacks_executed() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        let register = |condition| HandleMsg::RegisterCallback {
+            condition,
+            contract_addr: HumanAddr::from("lottery"),
+            callback_code_hash: "abcd".to_string(),
+            msg: Binary::from(format!("{:?}", condition).as_bytes()),
+        };
+
+        // any address can register callbacks, as long as they aren't already due
+        let env = mock_env("registrant", &[]);
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            register(CallbackCondition::Height(1000)),
+        );
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Callback condition Height(1000) is already met by the tip at height 1000"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            register(CallbackCondition::Height(1002)),
+        )
+        .unwrap();
+        let value: RegisterCallbackResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(0, value.id);
+        let _res = handle(
+            &mut deps,
+            env.clone(),
+            register(CallbackCondition::Height(1004)),
+        )
+        .unwrap();
+        let _res = handle(&mut deps, env, register(CallbackCondition::Height(1003))).unwrap();
+
+        // only the registrant or the owner can cancel a callback
+        let env = mock_env("anyone", &[]);
+        let res = handle(&mut deps, env, HandleMsg::CancelCallback { id: 1 });
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("registrant", &[]);
+        let _res = handle(&mut deps, env, HandleMsg::CancelCallback { id: 1 }).unwrap();
+
+        let res = query(
+            &deps,
+            QueryMsg::GetCallbacks {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: CallbacksResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            vec![0, 2],
+            value.callbacks.iter().map(|c| c.id).collect::<Vec<u64>>()
+        );
+        assert_eq!(HumanAddr::from("registrant"), value.callbacks[0].registrant);
+        assert!(!value.callbacks[0].due);
+
+        let env = mock_env("anyone", &[]);
+        let res = handle(&mut deps, env, HandleMsg::TriggerCallbacks { id: None });
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "No callbacks are due"),
+            _ => panic!("Must return an error"),
+        }
+
+        // the update to height 1003 executes both remaining callbacks, oldest first
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 3);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: HumanAddr::from("lottery"),
+                    callback_code_hash: "abcd".to_string(),
+                    msg: Binary::from("Height(1002)".as_bytes()),
+                    send: vec![],
+                }),
+                CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: HumanAddr::from("lottery"),
+                    callback_code_hash: "abcd".to_string(),
+                    msg: Binary::from("Height(1003)".as_bytes()),
+                    send: vec![],
+                }),
+            ],
+            res.messages
+        );
+
+        let res = query(
+            &deps,
+            QueryMsg::GetCallbacks {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: CallbacksResponse = from_binary(&res).unwrap();
+        assert!(value.callbacks.is_empty());
+        let env = mock_env("registrant", &[]);
+        let res = handle(&mut deps, env, HandleMsg::CancelCallback { id: 0 });
+        match res {
+            Err(StdError::NotFound { .. }) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn callbacks_limited() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // callbacks on time are due once the median time past reaches it
+        let register = HandleMsg::RegisterCallback {
+            condition: CallbackCondition::Time(1500000001),
+            contract_addr: HumanAddr::from("lottery"),
+            callback_code_hash: "abcd".to_string(),
+            msg: Binary::from("{}".as_bytes()),
+        };
+        let env = mock_env("registrant", &[]);
+        for _ in 0..MAX_CALLBACKS_PER_REGISTRANT {
+            let _res = handle(&mut deps, env.clone(), register.clone()).unwrap();
+        }
+        let res = handle(&mut deps, env, register.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "registrant already has 10 pending callbacks, the most allowed"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        let env = mock_env("other", &[]);
+        for _ in 0..2 {
+            let _res = handle(&mut deps, env.clone(), register.clone()).unwrap();
+        }
+
+        // the update that makes them due executes as many as it can
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 2);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(MAX_CALLBACKS_PER_UPDATE, res.messages.len());
+        let msg = QueryMsg::GetCallbacks {
+            start_after: None,
+            limit: None,
+        };
+        let value: CallbacksResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![10, 11],
+            value.callbacks.iter().map(|c| c.id).collect::<Vec<u64>>()
+        );
+        assert!(value.callbacks[0].due);
+
+        // anyone can execute the rest
+        let msg = HandleMsg::TriggerCallbacks { id: None };
+        let res = handle(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(2, res.messages.len());
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "No callbacks are due"),
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn failing_callback_does_not_block_updates() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // the first callback's target always fails
+        let env = mock_env("registrant", &[]);
+        for contract in ["broken", "lottery"].iter() {
+            let msg = HandleMsg::RegisterCallback {
+                condition: CallbackCondition::Height(1002),
+                contract_addr: HumanAddr::from(*contract),
+                callback_code_hash: "abcd".to_string(),
+                msg: Binary::from("{}".as_bytes()),
+            };
+            let _res = handle(&mut deps, env.clone(), msg).unwrap();
+        }
+
+        // executing it would revert the update, so the relayer skips the callbacks
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 4);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[..2].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: Some(true),
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        // later updates don't execute callbacks that were already due
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[2..].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        assert!(res.messages.is_empty());
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(4, value.curr_offset);
+
+        // the other callback can still be executed by its id
+        let res = handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::TriggerCallbacks { id: Some(1) },
+        )
+        .unwrap();
+        match &res.messages[..] {
+            [CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. })] => {
+                assert_eq!(&HumanAddr::from("lottery"), contract_addr)
+            }
+            _ => panic!("Must execute the callback"),
+        }
+        let res = handle(&mut deps, env, HandleMsg::TriggerCallbacks { id: Some(1) });
+        match res {
+            Err(StdError::NotFound { .. }) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let msg = QueryMsg::GetCallbacks {
+            start_after: None,
+            limit: None,
+        };
+        let value: CallbacksResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![0],
+            value.callbacks.iter().map(|c| c.id).collect::<Vec<u64>>()
+        );
+    }
+
+    #[test]
+    fn update_length_limits() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let mut msg = easy_init_msg();
+        msg.max_update_length = Some(1);
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env.clone(), msg.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Maximum update length 1 is less than the minimum update length 2"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        msg.max_update_length = Some(3);
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 4);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: Some(true),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "too_many_headers: Number of blocks provided (4) is more than the maximum allowed (3)"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        // the limit can be lifted
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: None,
+            max_update_length: Some(0),
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(4, value.curr_offset);
+        assert_eq!(0, value.max_update_length);
+    }
+
+    #[test]
+    fn update_accept_prefix() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // the third header doesn't follow the second
+        let mut chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 4);
+        chain[2] = mine_header(&"11".repeat(32), &"aa".repeat(32), 1500001800, EASY_BITS);
+
+        // without accept_prefix nothing is accepted
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert!(msg.starts_with("prev_hash_mismatch[2]: "))
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // with it the first two are, and the log reports why the third wasn't
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: Some(true),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let result: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        let reason = format!(
+            "Previous block header hash {} is not equal to value in header {}",
+            double_hash_hex(&chain[1]),
+            "11".repeat(32)
+        );
+        assert_eq!(
+            UpdateResult {
+                headers_skipped: 0,
+                headers_accepted: 2,
+                tip_height: 1002,
+                tip_hash: double_hash_hex(&chain[1]),
+                tip_time: 1500001200,
+                rejected: Some(RejectedHeader {
+                    index: 2,
+                    code: "prev_hash_mismatch".to_string(),
+                    reason: reason.clone(),
+                }),
+            },
+            result
+        );
+        assert!(res.log.contains(&log("rejected_index", 2)));
+        assert!(res
+            .log
+            .contains(&log("rejected_code", "prev_hash_mismatch")));
+        assert!(res.log.contains(&log("rejected_reason", reason)));
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.curr_offset);
+
+        // a valid prefix shorter than the minimum isn't accepted
+        let more = mine_chain(&double_hash_hex(&chain[1]), &"bb".repeat(32), 1500001200, 1);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: vec![more[0].clone(), chain[3].clone()],
+            accept_prefix: Some(true),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert!(msg.starts_with("prev_hash_mismatch[1]: "))
+            }
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn update_skips_known_headers() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 5);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[..3].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+
+        // a second relayer's batch overlaps the first one's
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[1..].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let result: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!((2, 2), (result.headers_skipped, result.headers_accepted));
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.curr_offset);
+        assert_eq!(double_hash_hex(&chain[4]), value.curr_hash);
+
+        // a batch that was entirely accepted already changes nothing
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain[3..].to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg).unwrap();
+        let result: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!((2, 0), (result.headers_skipped, result.headers_accepted));
+        assert_eq!(1005, result.tip_height);
+
+        // known headers that don't end at the tip aren't skipped, so the batch is
+        // rejected where it stops following the chain
+        let more = mine_chain(&double_hash_hex(&chain[4]), &"aa".repeat(32), 1500003000, 2);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: vec![chain[3].clone(), more[0].clone(), more[1].clone()],
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert!(msg.starts_with("prev_hash_mismatch[0]: "))
+            }
+            _ => panic!("Must return an error"),
+        }
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.curr_offset);
+
+        // the new headers still have to meet the minimum update length
+        let more = mine_chain(&double_hash_hex(&chain[4]), &"aa".repeat(32), 1500003000, 1);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: vec![chain[3].clone(), chain[4].clone(), more[0].clone()],
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "too_few_headers: Number of blocks provided (1) is less than minimum required (2)"
+            ),
+            _ => panic!("Must return an error"),
         }
     }
 
-    fn test_block_headers() -> Vec<String> {
-        // Values are encoded as hex strings in little endian format.
-        vec![
-            [
-                // version
-                "01000000",
-                // previous block hash
-                "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
-                // merkle root hash
-                "e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122b",
-                // unix timestamp
-                "c7f5d74d",
-                // difficulty bits
-                "f2b9441a",
-                // nonce
-                "42a14695",
-            ]
-            .concat(),
-            [
-                "01000000",
-                "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000",
-                "b371c14921b20c2895ed76545c116e0ad70167c5c4952ca201f5d544a26efb53",
-                "b4f6d74d",
-                "f2b9441a",
-                "071a0c81",
-            ]
-            .concat(),
-            [
-                "01000000",
-                "85afcb448a3fcde31dc78babd352d9dbde6fcb566777ea33051c000000000000",
-                "ca5b6b96fe65e1a7d50e7c3025a176472ba26d44512de86a6f3e39649330cd2f",
-                "16f7d74d",
-                "f2b9441a",
-                "8574adaf",
-            ]
-            .concat(),
-        ]
+    #[test]
+    fn update_logs_and_result() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, easy_init_msg()).unwrap();
+        assert_eq!(
+            vec![
+                log("action", "init"),
+                log("submitter", "creator"),
+                log("new_tip_height", 1000),
+                log("new_tip_hash", "00".repeat(32)),
+                log("new_tip_time", 1500000000),
+            ],
+            res.log
+        );
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 2);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(
+            vec![
+                log("action", "update_block_offset"),
+                log("submitter", "relayer"),
+                log("headers_skipped", 0),
+                log("headers_accepted", 2),
+                log("new_tip_height", 1002),
+                log("new_tip_hash", double_hash_hex(&chain[1])),
+                log("new_tip_time", 1500001200),
+                log("callbacks_executed", 0),
+            ],
+            res.log
+        );
+        let result: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            UpdateResult {
+                headers_skipped: 0,
+                headers_accepted: 2,
+                tip_height: 1002,
+                tip_hash: double_hash_hex(&chain[1]),
+                tip_time: 1500001200,
+                rejected: None,
+            },
+            result
+        );
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::ResetState {
+            new_state: easy_init_msg(),
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(log("action", "reset_state"), res.log[0]);
+        let result: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(1000, result.tip_height);
+        assert_eq!("00".repeat(32), result.tip_hash);
+
+        // messages that don't change the tip log what they did
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::SetAdmin {
+            address: HumanAddr::from("admin"),
+            permissions: vec![Permission::Reset, Permission::Pause],
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(
+            vec![
+                log("action", "set_admin"),
+                log("submitter", "creator"),
+                log("address", "admin"),
+                log("permissions", "reset,pause"),
+            ],
+            res.log
+        );
+        assert_eq!(None, res.data);
     }
 
     #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies(20, &[]);
+    fn time_lookups() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // the third header's timestamp is earlier than the second's
+        let mut chain = vec![];
+        let mut prev_hash = "00".repeat(32);
+        for time in [600, 1800, 1200, 2400, 3000].iter() {
+            let header = mine_header(&prev_hash, &"aa".repeat(32), 1500000000 + time, EASY_BITS);
+            prev_hash = double_hash_hex(&header);
+            chain.push(header);
+        }
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        fn height_at_time<S: Storage, A: Api, Q: Querier>(
+            deps: &Extern<S, A, Q>,
+            timestamp: u32,
+            use_mtp: Option<bool>,
+        ) -> StdResult<BlockTimeResponse> {
+            let msg = QueryMsg::HeightAtTime {
+                timestamp,
+                use_mtp,
+                confirmations: None,
+            };
+            let res = query(deps, msg)?;
+            Ok(from_binary(&res).unwrap())
+        }
+        let value = height_at_time(&deps, 1500001000, None).unwrap();
+        assert_eq!(
+            BlockTimeResponse {
+                height: 1002,
+                hash: double_hash_hex(&chain[1]),
+                time: 1500001800,
+                median_time_past: Some(1500000600),
+                paused: false,
+            },
+            value
+        );
+        assert_eq!(
+            1002,
+            height_at_time(&deps, 1500001500, None).unwrap().height
+        );
+        assert_eq!(
+            1004,
+            height_at_time(&deps, 1500001801, None).unwrap().height
+        );
+        assert_eq!(
+            1003,
+            height_at_time(&deps, 1500001200, Some(true))
+                .unwrap()
+                .height
+        );
+        assert_eq!(
+            1005,
+            height_at_time(&deps, 1500001201, Some(true))
+                .unwrap()
+                .height
+        );
+
+        match height_at_time(&deps, 1500003001, None) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "No block header up to the tip at height 1005 has a timestamp of at least 1500003001"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        match height_at_time(&deps, 1500000000, Some(true)) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "The first block header with a median time past of at least 1500000000 is before the stored header history, which starts at height 1000"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        let res = query(
+            &deps,
+            QueryMsg::TimeAtHeight {
+                height: 1003,
+                confirmations: None,
+            },
+        )
+        .unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(1500001200, value.time);
+        assert_eq!(Some(1500001200), value.median_time_past);
+        match query(
+            &deps,
+            QueryMsg::TimeAtHeight {
+                height: 1006,
+                confirmations: None,
+            },
+        ) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "Height 1006 is beyond the tip at height 1005")
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // only the retained history can be searched
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: Some(3),
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        match query(&deps, QueryMsg::TimeAtHeight {
+                height: 1002,
+                confirmations: None,
+            }) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Block header at height 1002 is not in the stored header history, which starts at height 1003"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        assert!(height_at_time(&deps, 1500001000, None).is_err());
+        assert_eq!(
+            1004,
+            height_at_time(&deps, 1500001801, None).unwrap().height
+        );
+    }
+
+    #[test]
+    fn finalized_tip_and_confirmations() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let mut msg = easy_init_msg();
+        msg.finality_depth = Some(2);
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // there aren't enough blocks above the start block yet
+        match query(&deps, QueryMsg::GetFinalizedTip {}) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "No stored block header has 3 confirmations with the tip at height 1000"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 5);
+        let env = mock_env("relayer", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetFinalizedTip {}).unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            BlockTimeResponse {
+                height: 1003,
+                hash: double_hash_hex(&chain[2]),
+                time: 1500001800,
+                median_time_past: Some(1500001200),
+                paused: false,
+            },
+            value
+        );
+
+        let msg = QueryMsg::GetCurrentTime {
+            confirmations: Some(3),
+        };
+        let value: CurrentTimeResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1003, value.tip_height);
+        assert_eq!(1500001800, value.tip_time);
+        assert_eq!(1500001200, value.median_time_past);
+
+        let msg = QueryMsg::HeightAtTime {
+            timestamp: 1500002400,
+            use_mtp: None,
+            confirmations: Some(3),
+        };
+        match query(&deps, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "No block header up to height 1003 with 3 confirmations has a timestamp of at least 1500002400"
+            ),
+            _ => panic!("Must return an error"),
+        }
+        let msg = QueryMsg::TimeAtHeight {
+            height: 1004,
+            confirmations: Some(3),
+        };
+        match query(&deps, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(
+                    msg,
+                    "Height 1004 is beyond height 1003 with 3 confirmations"
+                )
+            }
+            _ => panic!("Must return an error"),
+        }
+        let msg = QueryMsg::TimeAtHeight {
+            height: 1004,
+            confirmations: Some(2),
+        };
+        assert!(query(&deps, msg).is_ok());
+
+        // the finalized tip has to stay in the retained history
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: Some(2),
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        match handle(&mut deps, env, msg) {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(
+                    msg,
+                    "Finality depth 2 must be less than the header retention 2"
+                )
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // without header retention the finality depth isn't limited
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: None,
+            max_update_length: None,
+            finality_depth: Some(u32::MAX),
+            stale_threshold: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        match query(&deps, QueryMsg::GetFinalizedTip {}) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                format!(
+                    "No stored block header has {} confirmations with the tip at height 1005",
+                    u32::MAX
+                )
+            ),
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn status_reports_staleness() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let mut env = mock_env("creator", &coins(2, "token"));
+        env.block.height = 100;
+        env.block.time = 1500000100;
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        let msg = QueryMsg::GetStatus { block_time: None };
+        let value: StatusResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(
+            StatusResponse {
+                tip_height: 1000,
+                tip_time: 1500000000,
+                last_update_block_height: 100,
+                last_update_block_time: 1500000100,
+                block_time: 1500000100,
+                lag: 100,
+                stale_threshold: DEFAULT_STALE_THRESHOLD,
+                stale: false,
+                paused: false,
+            },
+            value
+        );
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 2);
+        let mut env = mock_env("relayer", &[]);
+        env.block.height = 110;
+        env.block.time = 1500001300;
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // an update that accepts no headers doesn't count as one
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: Some(0),
+            header_retention: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let mut env = mock_env("relayer", &[]);
+        env.block.height = 120;
+        env.block.time = 1500001400;
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: mine_chain(&"11".repeat(32), &"aa".repeat(32), 1500001200, 1),
+            accept_prefix: Some(true),
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let value: UpdateResult = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(0, value.headers_accepted);
+
+        let msg = QueryMsg::GetStatus { block_time: None };
+        let value: StatusResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(1002, value.tip_height);
+        assert_eq!(110, value.last_update_block_height);
+        assert_eq!(1500001300, value.last_update_block_time);
+        assert_eq!(100, value.lag);
+        assert!(!value.stale);
+
+        // the caller passes its own block time to see that relayers stopped
+        let msg = QueryMsg::GetStatus {
+            block_time: Some(1500001200 + DEFAULT_STALE_THRESHOLD + 1),
+        };
+        let value: StatusResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(DEFAULT_STALE_THRESHOLD + 1, value.lag);
+        assert!(value.stale);
+
+        // a tip ahead of the block time isn't behind at all
+        let msg = QueryMsg::GetStatus {
+            block_time: Some(1500001000),
+        };
+        let value: StatusResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(0, value.lag);
+
+        let env = mock_env("creator", &[]);
+        let msg = HandleMsg::UpdateConfig {
+            min_difficulty_bits: None,
+            min_update_length: None,
+            header_retention: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: Some(60),
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let msg = QueryMsg::GetStatus { block_time: None };
+        let value: StatusResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert!(value.stale);
+    }
+
+    #[test]
+    fn checkpoints_enforced() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, easy_init_msg()).unwrap();
+
+        // only the owner can add checkpoints
+        let msg = HandleMsg::AddCheckpoint {
+            height: 1002,
+            hash: "11".repeat(32),
+        };
+        let env = mock_env("anyone", &[]);
+        let res = handle(&mut deps, env, msg.clone());
+        match res {
+            Err(StdError::Unauthorized { .. }) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let env = mock_env("creator", &[]);
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+        let msg = HandleMsg::AddCheckpoint {
+            height: 1003,
+            hash: "11".repeat(31),
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Invalid checkpoint hash: Encoded length is 31 bytes, must be 32"
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        // a chain with a different hash at the checkpoint is rejected
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 3);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env.clone(), msg.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                format!(
+                    "checkpoint_mismatch[1]: Block header hash {} at height 1002 does not match checkpoint {}",
+                    double_hash_hex(&chain[1]),
+                    "11".repeat(32)
+                )
+            ),
+            _ => panic!("Must return an error"),
+        }
+
+        // with the right hash it's accepted
+        let fix = HandleMsg::AddCheckpoint {
+            height: 1002,
+            hash: double_hash_hex(&chain[1]),
+        };
+        let _res = handle(&mut deps, env.clone(), fix).unwrap();
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+
+        // checkpoints can't conflict with the stored chain
+        let msg = HandleMsg::AddCheckpoint {
+            height: 1001,
+            hash: "11".repeat(32),
+        };
+        let res = handle(&mut deps, env.clone(), msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert!(msg.contains("does not match")),
+            _ => panic!("Must return an error"),
+        }
+
+        for height in &[1010, 1005, 1020] {
+            let msg = HandleMsg::AddCheckpoint {
+                height: *height,
+                hash: "22".repeat(32),
+            };
+            let _res = handle(&mut deps, env.clone(), msg).unwrap();
+        }
+        let _res = handle(
+            &mut deps,
+            env.clone(),
+            HandleMsg::RemoveCheckpoint { height: 1010 },
+        )
+        .unwrap();
+        let res = handle(&mut deps, env, HandleMsg::RemoveCheckpoint { height: 1010 });
+        match res {
+            Err(StdError::NotFound { .. }) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        let res = query(
+            &deps,
+            QueryMsg::GetCheckpoints {
+                start_after: Some(1002),
+                limit: Some(1),
+            },
+        )
+        .unwrap();
+        let value: CheckpointsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            vec![Checkpoint {
+                height: 1005,
+                hash: "22".repeat(32),
+            }],
+            value.checkpoints
+        );
+        let res = query(
+            &deps,
+            QueryMsg::GetCheckpoints {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: CheckpointsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            vec![1002, 1005, 1020],
+            value
+                .checkpoints
+                .iter()
+                .map(|c| c.height)
+                .collect::<Vec<u32>>()
+        );
+    }
+
+    #[test]
+    fn migrate_from_v0() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
 
-        // The start_hash is a little endian hex string.
-        let start_hash: String = "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
-            .parse()
+        // a contract instantiated before versioning, 10 blocks after its start
+        let owner = deps
+            .api
+            .canonical_address(&HumanAddr::from("creator"))
             .unwrap();
-        let min_bits = bits_to_difficulty(0x1b0404cbu32);
+        singleton(&mut deps.storage, CONFIG_KEY)
+            .save(&StateV0 {
+                start_height: 990,
+                curr_offset: 10,
+                curr_hash: "00".repeat(32),
+                threshold_difficulty: format!("{:x}", bits_to_difficulty(EASY_BITS)),
+                min_update_length: 2,
+                owner,
+            })
+            .unwrap();
+        let res = query(&deps, QueryMsg::GetVersion {}).unwrap();
+        let value: VersionResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.version);
 
-        let env = mock_env("creator", &coins(1000, "earth"));
+        let env = mock_env("creator", &[]);
+        let res = migrate(&mut deps, env.clone(), MigrateMsg { from_v0: None });
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "from_v0 is required to migrate from version 0")
+            }
+            _ => panic!("Must return an error"),
+        }
+        let msg = MigrateMsg {
+            from_v0: Some(MigrateFromV0 {
+                curr_bits: EASY_BITS,
+                curr_time: 1500000000,
+                epoch_start_time: 1500000000,
+                prev_timestamps: vec![],
+                header_retention: 0,
+                curr_chainwork: "0".to_string(),
+            }),
+        };
+        let _res = migrate(&mut deps, env.clone(), msg).unwrap();
 
-        // we can just call .unwrap() to assert this was a success
-        let res = init(&mut deps, env, default_init_msg()).unwrap();
-        assert_eq!(0, res.messages.len());
+        let res = query(&deps, QueryMsg::GetVersion {}).unwrap();
+        let value: VersionResponse = from_binary(&res).unwrap();
+        assert_eq!(CONTRACT_VERSION, value.version);
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(Network::Mainnet, state.network);
+        assert_eq!(EASY_BITS, state.epoch_bits);
+        assert_eq!(DEFAULT_STALE_THRESHOLD, state.stale_threshold);
+        let res = query(&deps, QueryMsg::GetHeader { height: 1000 }).unwrap();
+        let value: HeaderResponse = from_binary(&res).unwrap();
+        assert_eq!("00".repeat(32), value.hash);
+        let res = query(&deps, QueryMsg::GetAdmins {}).unwrap();
+        let value: AdminsResponse = from_binary(&res).unwrap();
+        assert_eq!(HumanAddr::from("creator"), value.owner);
 
-        // it worked, let's query the state
+        // the migrated contract accepts updates, and migrating again changes nothing
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 2);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+        let _res = migrate(&mut deps, env, MigrateMsg { from_v0: None }).unwrap();
         let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
         let value: InfoResponse = from_binary(&res).unwrap();
-        assert_eq!(start_hash, value.curr_hash);
-        assert_eq!(0, value.curr_offset);
-        assert_eq!(125551, value.start_height);
-        assert_eq!(format!("{:x}", min_bits), value.min_difficulty);
-        assert_eq!(3, value.min_update_length)
+        assert_eq!(12, value.curr_offset);
+
+        // the header history starts at the migrated tip
+        let msg = QueryMsg::HeightAtTime {
+            timestamp: 1500000600,
+            use_mtp: None,
+            confirmations: None,
+        };
+        let res = query(&deps, msg).unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(1001, value.height);
+        let msg = QueryMsg::TimeAtHeight {
+            height: 995,
+            confirmations: None,
+        };
+        match query(&deps, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Block header at height 995 is not in the stored header history, which starts at height 1000"
+            ),
+            _ => panic!("Must return an error"),
+        }
     }
 
     #[test]
-    fn double_hash_test() {
-        // See https://en.bitcoin.it/wiki/Block_hashing_algorithm.
-        let a = "01000000";
-        let b = "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000";
-        let c = "e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122b";
-        let d = "c7f5d74d";
-        let e = "f2b9441a";
-        let f = "42a14695";
-        let result: String = [a, b, c, d, e, f].join("");
-        let hashed = double_hash_hex(&result);
-        assert_eq!(
-            hashed,
-            "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000"
-        )
+    fn migrate_finds_oldest_header() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let env = mock_env("creator", &[]);
+        let _res = init(&mut deps, env.clone(), easy_init_msg()).unwrap();
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 4);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+
+        // a version 7 contract whose retention was raised after pruning 1000 and 1001
+        headers(&mut deps.storage).remove(&height_key(1000));
+        headers(&mut deps.storage).remove(&height_key(1001));
+        config(&mut deps.storage)
+            .update(|mut state| {
+                state.header_retention = 10;
+                state.oldest_height = 0;
+                Ok(state)
+            })
+            .unwrap();
+        version(&mut deps.storage).save(&7).unwrap();
+
+        let _res = migrate(&mut deps, env, MigrateMsg { from_v0: None }).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1002, state.oldest_height);
+        let msg = QueryMsg::HeightAtTime {
+            timestamp: 1500001800,
+            use_mtp: None,
+            confirmations: None,
+        };
+        let res = query(&deps, msg).unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(1003, value.height);
     }
 
-    // TODO: add more tests
-    /*
     #[test]
-    fn parse_bits_test() {}
+    fn migrate_skips_missing_headers() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let env = mock_env("creator", &[]);
+        let _res = init(&mut deps, env.clone(), easy_init_msg()).unwrap();
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 8);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
 
-    #[test]
-    fn bits_to_difficulty_test() {}
-     */
+        // a version 7 contract whose retention was lowered to 3, after which the
+        // update that stored 1008 only removed 1005
+        headers(&mut deps.storage).remove(&height_key(1005));
+        config(&mut deps.storage)
+            .update(|mut state| {
+                state.header_retention = 3;
+                state.oldest_height = 0;
+                Ok(state)
+            })
+            .unwrap();
+        version(&mut deps.storage).save(&7).unwrap();
+
+        let _res = migrate(&mut deps, env, MigrateMsg { from_v0: None }).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1006, state.oldest_height);
+
+        // searches stay above the missing height
+        let msg = QueryMsg::HeightAtTime {
+            timestamp: 1500004000,
+            use_mtp: None,
+            confirmations: None,
+        };
+        let res = query(&deps, msg).unwrap();
+        let value: BlockTimeResponse = from_binary(&res).unwrap();
+        assert_eq!(1007, value.height);
+        let msg = QueryMsg::HeightAtTime {
+            timestamp: 1500003000,
+            use_mtp: None,
+            confirmations: None,
+        };
+        match query(&deps, msg) {
+            Err(StdError::GenericErr { .. }) => {}
+            _ => panic!("Must return an error"),
+        }
+        let msg = QueryMsg::TimeAtHeight {
+            height: 1004,
+            confirmations: None,
+        };
+        match query(&deps, msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Block header at height 1004 is not in the stored header history, which starts at height 1006"
+            ),
+            _ => panic!("Must return an error"),
+        }
+    }
 
     #[test]
-    fn update() {
+    fn min_difficulty_enforced() {
         let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let msg = InitMsg {
+            start_height: 125551,
+            min_difficulty_bits: 0x1a44b9f1u32,
+            start_hash: "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
+                .parse()
+                .unwrap(),
+            min_update_length: 3,
+            start_bits: 0x1a44b9f2u32,
+            start_time: 1305998000,
+            epoch_start_time: 1305000000,
+            prev_timestamps: vec![],
+            header_retention: 0,
+            start_chainwork: "0".to_string(),
+            network: None,
+            epoch_start_bits: None,
+            max_update_length: None,
+            finality_depth: None,
+            stale_threshold: None,
+        };
 
         let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+        let _res = init(&mut deps, env, msg).unwrap();
 
         // anyone can update
         let env = mock_env("anyone", &coins(2, "token"));
         let msg = HandleMsg::UpdateBlockOffset {
             block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
-        let _res = handle(&mut deps, env, msg).unwrap();
-
-        // should increase offset by 3
-        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
-        let value: InfoResponse = from_binary(&res).unwrap();
-        assert_eq!(3, value.curr_offset);
-        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
-        assert_eq!(
-            "1e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df1211000000000000",
-            value.curr_hash
-        );
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(msg, "difficulty_above_threshold[0]: Block difficulty 44b9f20000000000000000000000000000000000000000000000 cannot be greater than threshold 44b9f10000000000000000000000000000000000000000000000");
+            }
+            _ => panic!("Must return an error"),
+        }
     }
 
     #[test]
-    fn reset() {
+    fn retarget_enforced() {
         let mut deps = mock_dependencies(20, &coins(2, "token"));
 
+        // Pretend the first header starts a new difficulty epoch. The previous
+        // epoch took exactly two weeks, so the bits must stay the same.
+        let mut msg = default_init_msg();
+        msg.start_height = 2015;
+        msg.epoch_start_time = msg.start_time - Network::Mainnet.params().target_timespan;
         let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+        let _res = init(&mut deps, env, msg).unwrap();
 
-        // anyone can update
         let env = mock_env("anyone", &coins(2, "token"));
         let msg = HandleMsg::UpdateBlockOffset {
             block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
         let _res = handle(&mut deps, env, msg).unwrap();
 
-        // should increase offset by 3
-        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
-        let value: InfoResponse = from_binary(&res).unwrap();
-        assert_eq!(3, value.curr_offset);
-        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
-        assert_eq!(
-            "1e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df1211000000000000",
-            value.curr_hash
-        );
+        // The first header becomes the start of the new epoch.
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(0x4dd7f5c7, state.epoch_start_time);
+        assert_eq!(0x4dd7f716, state.curr_time);
+        assert_eq!(0x1a44b9f2, state.curr_bits);
 
-        // not anyone can reset
-        let unauth_env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::ResetState {
-            new_state: default_init_msg(),
+        // If the previous epoch only took one week the difficulty must double.
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let mut msg = default_init_msg();
+        msg.start_height = 2015;
+        msg.epoch_start_time = msg.start_time - Network::Mainnet.params().target_timespan / 2;
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
-        let res = handle(&mut deps, unauth_env, msg);
+        let res = handle(&mut deps, env, msg);
         match res {
-            Err(StdError::Unauthorized { .. }) => {}
-            _ => panic!("Must return unauthorized error"),
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(
+                    msg,
+                    "unexpected_bits[0]: Block difficulty bits 1a44b9f2 at height 2016 do not match expected bits 1a225cf9"
+                );
+            }
+            _ => panic!("Must return an error"),
         }
 
-        // only the original creator can reset the state
-        let auth_env = mock_env("creator", &coins(2, "token"));
+        // Outside of an epoch boundary the bits must match the current bits.
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
         let mut msg = default_init_msg();
-        msg.min_difficulty_bits = 0x11deadbfu32;
-        msg.min_update_length = 42;
-        msg.start_height = 42;
-        let msg = HandleMsg::ResetState { new_state: msg };
-        let _res = handle(&mut deps, auth_env, msg).unwrap();
+        msg.start_bits = 0x1a44b9f1;
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
 
-        // should now be the original values
-        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
-        let value: InfoResponse = from_binary(&res).unwrap();
-        assert_eq!(0, value.curr_offset);
-        // big-endian: 0000000000001112dff6e2a85b35d4f7ab7005b1b749282eeb1fdf094722601e
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(
+                    msg,
+                    "unexpected_bits[0]: Block difficulty bits 1a44b9f2 at height 125552 do not match expected bits 1a44b9f1"
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn network_bits_rules() {
+        let tip = ChainTip {
+            height: 1000,
+            hash: "00".repeat(32),
+            bits: 0x1d00ffff,
+            time: 1500000000,
+            epoch_start_time: 1499000000,
+            epoch_bits: 0x1c05a3f4,
+            recent_times: vec![1500000000],
+            max_time: 1500000000,
+            chainwork: U256::zero(),
+        };
+
+        // On testnet a block more than 20 minutes after the previous one can use the
+        // pow limit, and otherwise goes back to the epoch's bits.
+        let testnet = Network::Testnet.params();
+        assert_eq!(0x1d00ffff, expected_bits(&testnet, &tip, 1001, 1500001201));
+        assert_eq!(0x1c05a3f4, expected_bits(&testnet, &tip, 1001, 1500001200));
+        // Mainnet never allows it.
+        let mainnet = Network::Mainnet.params();
+        assert_eq!(0x1d00ffff, expected_bits(&mainnet, &tip, 1001, 1500001201));
+        let mut mainnet_tip = tip;
+        mainnet_tip.bits = 0x1c05a3f4;
         assert_eq!(
-            "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000",
-            value.curr_hash
+            0x1c05a3f4,
+            expected_bits(&mainnet, &mainnet_tip, 1001, 1500001201)
+        );
+        assert_ne!(
+            0x1c05a3f4,
+            expected_bits(&mainnet, &mainnet_tip, 2016, 1500001201)
+        );
+        // Regtest doesn't retarget at epoch boundaries.
+        let regtest = Network::Regtest.params();
+        assert_eq!(
+            0x1c05a3f4,
+            expected_bits(&regtest, &mainnet_tip, 2016, 1500000600)
         );
-        assert_eq!(42, value.min_update_length);
-        assert_eq!(42, value.start_height);
-        assert_eq!("5eadbf0000000000000000000000000000", value.min_difficulty);
     }
 
     #[test]
-    fn min_difficulty_enforced() {
+    fn regtest_no_retargeting() {
+        // The headers cross an epoch boundary. On regtest the bits stay the same.
         let mut deps = mock_dependencies(20, &coins(2, "token"));
-        let msg = InitMsg {
-            start_height: 125551,
-            min_difficulty_bits: 0x1a44b9f1u32,
-            start_hash: "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000"
-                .parse()
-                .unwrap(),
-            min_update_length: 3,
+        let mut msg = easy_init_msg();
+        msg.start_height = 2015;
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let chain = mine_chain(&"00".repeat(32), &"aa".repeat(32), 1500000000, 2);
+        let env = mock_env("anyone", &[]);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain.clone(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
+        let _res = handle(&mut deps, env.clone(), msg).unwrap();
+        let res = query(&deps, QueryMsg::GetContractInfo {}).unwrap();
+        let value: InfoResponse = from_binary(&res).unwrap();
+        assert_eq!(Network::Regtest, value.network);
+        assert_eq!(2, value.curr_offset);
 
+        // Mainnet rules retarget to the pow limit, which the headers don't declare.
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let mut msg = easy_init_msg();
+        msg.start_height = 2015;
+        msg.network = None;
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: chain,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let env = mock_env("anyone", &[]);
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(
+                    msg,
+                    "unexpected_bits[0]: Block difficulty bits 207fffff at height 2016 do not match expected bits 1d00ffff"
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn timestamps_enforced() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        // The median of the previous timestamps is later than the first header.
+        let mut msg = default_init_msg();
+        msg.prev_timestamps = vec![1305999000; 10];
         let env = mock_env("creator", &coins(2, "token"));
         let _res = init(&mut deps, env, msg).unwrap();
 
-        // anyone can update
         let env = mock_env("anyone", &coins(2, "token"));
         let msg = HandleMsg::UpdateBlockOffset {
             block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, backtrace: _ }) => {
+                assert_eq!(
+                    msg,
+                    "timestamp_too_early[0]: Block timestamp 1305998791 at height 125552 must be greater than median time past 1305999000"
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // Headers can't be more than two hours ahead of the current time.
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, default_init_msg()).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time = 1305998791 - MAX_FUTURE_BLOCK_TIME - 1;
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
         let res = handle(&mut deps, env, msg);
         match res {
             Err(StdError::GenericErr { msg, backtrace: _ }) => {
-                assert_eq!(msg, "Block difficulty 44b9f20000000000000000000000000000000000000000000000 cannot be greater than threshold 44b9f10000000000000000000000000000000000000000000000");
+                assert_eq!(
+                    msg,
+                    "timestamp_too_late[0]: Block timestamp 1305998791 at height 125552 is more than 7200 seconds ahead of the current time 1305991590"
+                );
             }
             _ => panic!("Must return an error"),
         }
+
+        // Accepted timestamps are added to the rolling window.
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: test_block_headers(),
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let recent_times = timestamps_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            recent_times,
+            vec![1305998000, 1305998791, 1305999028, 1305999126]
+        );
     }
 
     #[test]
@@ -491,13 +5079,15 @@ mod tests {
         let env = mock_env("anyone", &coins(2, "token"));
         let msg = HandleMsg::UpdateBlockOffset {
             block_headers: test_block_headers().split_last().unwrap().1.to_vec(),
+            accept_prefix: None,
+            skip_callbacks: None,
         };
         let res = handle(&mut deps, env, msg);
         match res {
             Err(StdError::GenericErr { msg, backtrace: _ }) => {
                 assert_eq!(
                     msg,
-                    "Number of blocks provided (2) is less than minimum required (3)"
+                    "too_few_headers: Number of blocks provided (2) is less than minimum required (3)"
                 );
             }
             _ => panic!("Must return an error"),
@@ -516,13 +5106,53 @@ mod tests {
         partial_blocks.push("bad_header".to_string());
         let msg = HandleMsg::UpdateBlockOffset {
             block_headers: partial_blocks,
+            accept_prefix: None,
+            skip_callbacks: None,
         };
         let res = handle(&mut deps, env, msg);
         match res {
             Err(StdError::GenericErr { msg, backtrace: _ }) => {
-                assert_eq!(msg, "Encoded block header length is 10, must be 160");
+                assert_eq!(
+                    msg,
+                    "bad_header_length[2]: Encoded block header length is 10, must be 160"
+                );
+            }
+            _ => panic!("Must return an error"),
+        }
+
+        // headers must be hex
+        let env = mock_env("anyone", &coins(2, "token"));
+        let mut bad_blocks = test_block_headers();
+        bad_blocks[0] = bad_blocks[0].replace("c7f5d74d", "c7f5d74z");
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: bad_blocks,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert!(msg.starts_with("bad_header_encoding[0]: "))
             }
             _ => panic!("Must return an error"),
         }
+
+        // the index of the failing header is reported
+        let env = mock_env("anyone", &coins(2, "token"));
+        let mut swapped_blocks = test_block_headers();
+        swapped_blocks.swap(1, 2);
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers: swapped_blocks,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let res = handle(&mut deps, env, msg);
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "prev_hash_mismatch[1]: Previous block header hash 1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000 is not equal to value in header 85afcb448a3fcde31dc78babd352d9dbde6fcb566777ea33051c000000000000"
+            ),
+            _ => panic!("Must return an error"),
+        }
     }
 }
@@ -0,0 +1,223 @@
+use cosmwasm_std::StdError;
+use snafu::{Backtrace, GenerateBacktrace, Snafu};
+
+// Errors from verifying block headers. Errors about a specific header carry its
+// index in the list of headers that was passed in.
+//
+// They are returned to callers as StdError::GenericErr with a message of the form
+// "<code>[<index>]: <description>", or "<code>: <description>" when the error isn't
+// about a specific header, so that relayers can tell them apart without matching
+// on the description. Codes don't change once added.
+#[derive(Snafu, Debug)]
+pub enum ContractError {
+    #[snafu(display(
+        "Number of blocks provided ({}) is less than minimum required ({})",
+        provided,
+        required
+    ))]
+    TooFewHeaders { provided: u32, required: u32 },
+
+    #[snafu(display(
+        "Number of blocks provided ({}) is more than the maximum allowed ({})",
+        provided,
+        allowed
+    ))]
+    TooManyHeaders { provided: u32, allowed: u32 },
+
+    #[snafu(display("Encoded block header length is {}, must be {}", length, expected))]
+    BadHeaderLength {
+        index: usize,
+        length: usize,
+        expected: usize,
+    },
+
+    #[snafu(display("Could not decode block header: {}", reason))]
+    BadHeaderEncoding { index: usize, reason: String },
+
+    // No longer returned, since headers are decoded as a whole and reported as
+    // BadHeaderEncoding. Kept so that its code isn't reused.
+    #[snafu(display("Could not parse difficulty bits \"{}\" into u32: {}", bits, reason))]
+    BadBits {
+        index: usize,
+        bits: String,
+        reason: String,
+    },
+
+    #[snafu(display(
+        "Block difficulty {} cannot be greater than threshold {}",
+        difficulty,
+        threshold
+    ))]
+    DifficultyAboveThreshold {
+        index: usize,
+        difficulty: String,
+        threshold: String,
+    },
+
+    #[snafu(display(
+        "Block difficulty bits {:08x} at height {} do not match expected bits {:08x}",
+        bits,
+        height,
+        expected
+    ))]
+    UnexpectedBits {
+        index: usize,
+        height: u32,
+        bits: u32,
+        expected: u32,
+    },
+
+    // No longer returned, since headers are decoded as a whole and reported as
+    // BadHeaderEncoding. Kept so that its code isn't reused.
+    #[snafu(display("Could not parse timestamp \"{}\" into u32: {}", time, reason))]
+    BadTimestamp {
+        index: usize,
+        time: String,
+        reason: String,
+    },
+
+    #[snafu(display(
+        "Block timestamp {} at height {} must be greater than median time past {}",
+        time,
+        height,
+        median_time
+    ))]
+    TimestampTooEarly {
+        index: usize,
+        height: u32,
+        time: u32,
+        median_time: u32,
+    },
+
+    #[snafu(display(
+        "Block timestamp {} at height {} is more than {} seconds ahead of the current time {}",
+        time,
+        height,
+        max_drift,
+        current_time
+    ))]
+    TimestampTooLate {
+        index: usize,
+        height: u32,
+        time: u32,
+        max_drift: u64,
+        current_time: u64,
+    },
+
+    #[snafu(display(
+        "Previous block header hash {} is not equal to value in header {}",
+        expected,
+        actual
+    ))]
+    PrevHashMismatch {
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+
+    #[snafu(display(
+        "Block header hash {} must be less than block difficulty {}",
+        hash,
+        difficulty
+    ))]
+    InsufficientWork {
+        index: usize,
+        hash: String,
+        difficulty: String,
+    },
+
+    #[snafu(display(
+        "Fork chainwork {} must be greater than current chainwork {}",
+        fork_chainwork,
+        chainwork
+    ))]
+    InsufficientForkWork {
+        fork_chainwork: String,
+        chainwork: String,
+    },
+
+    #[snafu(display(
+        "Block header hash {} at height {} does not match checkpoint {}",
+        actual,
+        height,
+        expected
+    ))]
+    CheckpointMismatch {
+        index: usize,
+        height: u32,
+        expected: String,
+        actual: String,
+    },
+
+    #[snafu(display("Block header updates are paused"))]
+    Paused,
+
+    // Errors that aren't about the headers, like failing to load the contract state.
+    // These are passed through unchanged.
+    #[snafu(display("{}", source))]
+    Std { source: StdError },
+}
+
+impl ContractError {
+    // A stable identifier for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ContractError::TooFewHeaders { .. } => "too_few_headers",
+            ContractError::TooManyHeaders { .. } => "too_many_headers",
+            ContractError::BadHeaderLength { .. } => "bad_header_length",
+            ContractError::BadHeaderEncoding { .. } => "bad_header_encoding",
+            ContractError::BadBits { .. } => "bad_bits",
+            ContractError::DifficultyAboveThreshold { .. } => "difficulty_above_threshold",
+            ContractError::UnexpectedBits { .. } => "unexpected_bits",
+            ContractError::BadTimestamp { .. } => "bad_timestamp",
+            ContractError::TimestampTooEarly { .. } => "timestamp_too_early",
+            ContractError::TimestampTooLate { .. } => "timestamp_too_late",
+            ContractError::PrevHashMismatch { .. } => "prev_hash_mismatch",
+            ContractError::InsufficientWork { .. } => "insufficient_work",
+            ContractError::InsufficientForkWork { .. } => "insufficient_fork_work",
+            ContractError::CheckpointMismatch { .. } => "checkpoint_mismatch",
+            ContractError::Paused => "paused",
+            ContractError::Std { .. } => "std",
+        }
+    }
+
+    // The index of the header that failed verification, if the error is about one.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            ContractError::BadHeaderLength { index, .. }
+            | ContractError::BadHeaderEncoding { index, .. }
+            | ContractError::BadBits { index, .. }
+            | ContractError::DifficultyAboveThreshold { index, .. }
+            | ContractError::UnexpectedBits { index, .. }
+            | ContractError::BadTimestamp { index, .. }
+            | ContractError::TimestampTooEarly { index, .. }
+            | ContractError::TimestampTooLate { index, .. }
+            | ContractError::PrevHashMismatch { index, .. }
+            | ContractError::InsufficientWork { index, .. }
+            | ContractError::CheckpointMismatch { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl From<StdError> for ContractError {
+    fn from(source: StdError) -> Self {
+        ContractError::Std { source }
+    }
+}
+
+impl From<ContractError> for StdError {
+    fn from(err: ContractError) -> Self {
+        if let ContractError::Std { source } = err {
+            return source;
+        }
+        let msg = match err.index() {
+            Some(index) => format!("{}[{}]: {}", err.code(), index, err),
+            None => format!("{}: {}", err.code(), err),
+        };
+        StdError::GenericErr {
+            msg,
+            backtrace: Option::Some(Backtrace::generate()),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::convert::TryInto;
+use std::fmt;
+
+use primitive_types::U256;
+use rustc_hex::{FromHex, ToHex};
+use sha2::{Digest, Sha256};
+use snafu::Snafu;
+
+// Size of a serialized block header in bytes.
+pub const BLOCK_HEADER_SIZE: usize = 80;
+
+#[derive(Snafu, Debug, PartialEq)]
+pub enum HeaderError {
+    #[snafu(display("Encoded length is {} bytes, must be {}", length, expected))]
+    BadLength { length: usize, expected: usize },
+
+    #[snafu(display("Invalid hex: {}", reason))]
+    BadHex { reason: String },
+}
+
+// A double SHA256 hash, like a block hash or a merkle root, in internal byte order.
+//
+// Bitcoin serializes hashes in internal order, which is what the contract stores and
+// takes as "little endian" hex. Block explorers and Bitcoin Core's RPCs show them with
+// the bytes reversed, which is the display order and how Display formats them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BlockHash(pub [u8; 32]);
+
+impl BlockHash {
+    // Hex in internal byte order, like the hashes the contract stores.
+    pub fn from_internal_hex(hex: &str) -> Result<BlockHash, HeaderError> {
+        let bytes = decode_hex(hex)?;
+        if bytes.len() != 32 {
+            return Err(HeaderError::BadLength {
+                length: bytes.len(),
+                expected: 32,
+            });
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&bytes);
+        Ok(BlockHash(hash))
+    }
+
+    // Hex in display byte order, like the hashes shown by block explorers.
+    pub fn from_display_hex(hex: &str) -> Result<BlockHash, HeaderError> {
+        let mut hash = BlockHash::from_internal_hex(hex)?;
+        hash.0.reverse();
+        Ok(hash)
+    }
+
+    pub fn to_internal_hex(&self) -> String {
+        self.0.to_hex()
+    }
+
+    pub fn to_display_hex(&self) -> String {
+        let mut bytes = self.0;
+        bytes.reverse();
+        bytes.to_hex()
+    }
+
+    // The hash as a number, for comparing against a target.
+    pub fn to_u256(&self) -> U256 {
+        U256::from_little_endian(&self.0)
+    }
+}
+
+impl fmt::Display for BlockHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_display_hex())
+    }
+}
+
+// A Bitcoin block header. Fields are serialized in little endian, in this order.
+// See https://en.bitcoin.it/wiki/Block_hashing_algorithm.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_hash: BlockHash,
+    pub merkle_root: BlockHash,
+    pub time: u32,
+    // Compact encoding of the target.
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlockHeader, HeaderError> {
+        if bytes.len() != BLOCK_HEADER_SIZE {
+            return Err(HeaderError::BadLength {
+                length: bytes.len(),
+                expected: BLOCK_HEADER_SIZE,
+            });
+        }
+        let mut prev_hash = BlockHash::default();
+        prev_hash.0.copy_from_slice(&bytes[4..36]);
+        let mut merkle_root = BlockHash::default();
+        merkle_root.0.copy_from_slice(&bytes[36..68]);
+        Ok(BlockHeader {
+            version: i32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            prev_hash,
+            merkle_root,
+            time: u32::from_le_bytes(bytes[68..72].try_into().unwrap()),
+            bits: u32::from_le_bytes(bytes[72..76].try_into().unwrap()),
+            nonce: u32::from_le_bytes(bytes[76..80].try_into().unwrap()),
+        })
+    }
+
+    pub fn from_hex(hex: &str) -> Result<BlockHeader, HeaderError> {
+        BlockHeader::from_bytes(&decode_hex(hex)?)
+    }
+
+    pub fn to_bytes(&self) -> [u8; BLOCK_HEADER_SIZE] {
+        let mut bytes = [0u8; BLOCK_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..36].copy_from_slice(&self.prev_hash.0);
+        bytes[36..68].copy_from_slice(&self.merkle_root.0);
+        bytes[68..72].copy_from_slice(&self.time.to_le_bytes());
+        bytes[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        bytes[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.to_bytes()[..].to_hex()
+    }
+
+    // The double SHA256 hash of the serialized header.
+    pub fn hash(&self) -> BlockHash {
+        BlockHash(double_sha256(&self.to_bytes()))
+    }
+}
+
+// Hashes bytes twice with SHA256, the way Bitcoin hashes block headers and
+// transactions.
+pub fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let first = Sha256::digest(data);
+    Sha256::digest(&first).into()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, HeaderError> {
+    hex.from_hex().map_err(|err| HeaderError::BadHex {
+        reason: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Block 125552, see https://en.bitcoin.it/wiki/Block_hashing_algorithm.
+    const HEADER_HEX: &str = "0100000081cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122bc7f5d74df2b9441a42a14695";
+
+    #[test]
+    fn parse_header() {
+        let header = BlockHeader::from_hex(HEADER_HEX).unwrap();
+        assert_eq!(1, header.version);
+        assert_eq!(
+            "00000000000008a3a41b85b8b29ad444def299fee21793cd8b9e567eab02cd81",
+            header.prev_hash.to_display_hex()
+        );
+        assert_eq!(
+            "2b12fcf1b09288fcaff797d71e950e71ae42b91e8bdb2304758dfcffc2b620e3",
+            header.merkle_root.to_string()
+        );
+        assert_eq!(1305998791, header.time);
+        assert_eq!(0x1a44b9f2, header.bits);
+        assert_eq!(2504433986, header.nonce);
+
+        assert_eq!(HEADER_HEX, header.to_hex());
+        assert_eq!(
+            "00000000000000001e8d6829a8a21adc5d38d0a473b144b6765798e61f98bd1d",
+            header.hash().to_string()
+        );
+        assert_eq!(
+            "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000",
+            header.hash().to_internal_hex()
+        );
+    }
+
+    #[test]
+    fn block_hash_order() {
+        let hash = BlockHash::from_display_hex(
+            "00000000000000001e8d6829a8a21adc5d38d0a473b144b6765798e61f98bd1d",
+        )
+        .unwrap();
+        assert_eq!(
+            BlockHash::from_internal_hex(
+                "1dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000"
+            )
+            .unwrap(),
+            hash
+        );
+        assert_eq!(
+            U256::from_str_radix(
+                "00000000000000001e8d6829a8a21adc5d38d0a473b144b6765798e61f98bd1d",
+                16
+            )
+            .unwrap(),
+            hash.to_u256()
+        );
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(
+            Err(HeaderError::BadLength {
+                length: 79,
+                expected: 80
+            }),
+            BlockHeader::from_hex(&HEADER_HEX[..158])
+        );
+        match BlockHeader::from_hex(&HEADER_HEX.replace("c7f5d74d", "c7f5d74g")) {
+            Err(HeaderError::BadHex { .. }) => {}
+            _ => panic!("Must return an error"),
+        }
+        assert_eq!(
+            Err(HeaderError::BadLength {
+                length: 31,
+                expected: 32
+            }),
+            BlockHash::from_internal_hex(&"00".repeat(31))
+        );
+    }
+}
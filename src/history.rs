@@ -0,0 +1,111 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{StdResult, Storage};
+use cosmwasm_storage::{bucket, bucket_read, Bucket, ReadonlyBucket};
+
+pub static HEADERS_KEY: &[u8] = b"headers";
+pub static HEADER_HEIGHTS_KEY: &[u8] = b"header_heights";
+
+// A compact record of an accepted block header.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HeaderRecord {
+    // The hash of the block header, as a little endian hex string.
+    pub hash: String,
+    pub time: u32,
+    pub bits: u32,
+    // The merkle root of the block's transactions, as a little endian hex string.
+    // This isn't known for the start block, since only its hash is passed in at init.
+    pub merkle_root: Option<String>,
+    // Total work of the chain up to and including this block.
+    // Big endian hex representation of a U256.
+    pub chainwork: String,
+    // Median time past of the chain up to and including this block. Unlike block
+    // timestamps, this never decreases along the chain, so heights can be looked
+    // up by it. Not known for records stored before version 5.
+    #[serde(default)]
+    pub median_time_past: Option<u32>,
+    // Greatest timestamp of this block and the blocks before it, which never
+    // decreases either. Not known for records stored before version 5.
+    #[serde(default)]
+    pub max_time: Option<u32>,
+}
+
+// Block header records keyed by height.
+pub fn headers<S: Storage>(storage: &mut S) -> Bucket<S, HeaderRecord> {
+    bucket(HEADERS_KEY, storage)
+}
+
+pub fn headers_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, HeaderRecord> {
+    bucket_read(HEADERS_KEY, storage)
+}
+
+// Reverse index from block header hash to height.
+pub fn header_heights<S: Storage>(storage: &mut S) -> Bucket<S, u32> {
+    bucket(HEADER_HEIGHTS_KEY, storage)
+}
+
+pub fn header_heights_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, u32> {
+    bucket_read(HEADER_HEIGHTS_KEY, storage)
+}
+
+pub fn height_key(height: u32) -> [u8; 4] {
+    height.to_be_bytes()
+}
+
+// Stores the record for a block header at the given height, replacing any
+// record that was stored there before.
+pub fn store_header<S: Storage>(
+    storage: &mut S,
+    height: u32,
+    record: &HeaderRecord,
+) -> StdResult<()> {
+    remove_header(storage, height)?;
+    headers(storage).save(&height_key(height), record)?;
+    header_heights(storage).save(record.hash.as_bytes(), &height)
+}
+
+// Removes up to limit records, starting at the oldest height, that fall out of the
+// retained range below the tip. If retention is 0, nothing is removed. Returns the
+// new oldest height.
+pub fn prune_headers<S: Storage>(
+    storage: &mut S,
+    mut oldest: u32,
+    tip: u32,
+    retention: u32,
+    limit: usize,
+) -> StdResult<u32> {
+    let mut removed = 0;
+    while retention > 0 && tip - oldest >= retention && removed < limit {
+        remove_header(storage, oldest)?;
+        oldest += 1;
+        removed += 1;
+    }
+    Ok(oldest)
+}
+
+// Finds the oldest record of the run of stored records that ends at the tip, going
+// no lower than low. Records below a missing height aren't part of the run.
+pub fn find_oldest_header<S: Storage>(storage: &S, low: u32, tip: u32) -> StdResult<u32> {
+    let mut oldest = tip;
+    while oldest > low
+        && headers_read(storage)
+            .may_load(&height_key(oldest - 1))?
+            .is_some()
+    {
+        oldest -= 1;
+    }
+    Ok(oldest)
+}
+
+// Removes the record at the given height along with its reverse index entry.
+fn remove_header<S: Storage>(storage: &mut S, height: u32) -> StdResult<()> {
+    if let Some(old) = headers_read(storage).may_load(&height_key(height))? {
+        // Only remove the index entry if it still points at this height.
+        if header_heights_read(storage).may_load(old.hash.as_bytes())? == Some(height) {
+            header_heights(storage).remove(old.hash.as_bytes());
+        }
+        headers(storage).remove(&height_key(height));
+    }
+    Ok(())
+}
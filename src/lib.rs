@@ -1,12 +1,21 @@
+pub mod callbacks;
+pub mod checkpoints;
+pub mod client;
 pub mod contract;
+pub mod error;
+pub mod header;
+pub mod history;
 pub mod msg;
+pub mod network;
+#[cfg(feature = "relayer")]
+pub mod relayer;
 pub mod state;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", not(feature = "library")))]
 mod wasm {
     use super::contract;
     use cosmwasm_std::{
-        do_handle, do_init, do_query, ExternalApi, ExternalQuerier, ExternalStorage,
+        do_handle, do_init, do_migrate, do_query, ExternalApi, ExternalQuerier, ExternalStorage,
     };
 
     #[no_mangle]
@@ -27,6 +36,15 @@ mod wasm {
         )
     }
 
+    #[no_mangle]
+    extern "C" fn migrate(env_ptr: u32, msg_ptr: u32) -> u32 {
+        do_migrate(
+            &contract::migrate::<ExternalStorage, ExternalApi, ExternalQuerier>,
+            env_ptr,
+            msg_ptr,
+        )
+    }
+
     #[no_mangle]
     extern "C" fn query(msg_ptr: u32) -> u32 {
         do_query(
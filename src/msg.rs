@@ -1,12 +1,71 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
+
+use crate::callbacks::CallbackCondition;
+use crate::checkpoints::Checkpoint;
+use crate::network::Network;
+use crate::state::Permission;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
     pub start_height: u32,
     pub min_difficulty_bits: u32,
     pub min_update_length: u32,
     pub start_hash: String,
+    // Difficulty bits declared by the start block header.
+    pub start_bits: u32,
+    // Timestamp of the start block header.
+    pub start_time: u32,
+    // Timestamp of the first block header in the start block's difficulty epoch.
+    pub epoch_start_time: u32,
+    // Timestamps of the block headers preceding the start block, oldest first.
+    // Only the last 10 are used for the median time past check.
+    pub prev_timestamps: Vec<u32>,
+    // Number of most recent block headers to keep in the header history, 0 keeps all of them.
+    pub header_retention: u32,
+    // Total work of the chain up to and including the start block, as a big endian hex
+    // string. This is the chainwork reported by getblockheader in Bitcoin Core.
+    pub start_chainwork: String,
+    // The network that block headers are relayed from, mainnet if not given.
+    pub network: Option<Network>,
+    // Difficulty bits of the first block header in the start block's epoch. Only needed
+    // when the start block is a testnet minimum difficulty block, defaults to start_bits.
+    pub epoch_start_bits: Option<u32>,
+    // The most block headers accepted in a single update, no limit if not given or 0.
+    pub max_update_length: Option<u32>,
+    // Number of blocks below the tip that GetFinalizedTip reports, 0 if not given. Must be
+    // less than header_retention unless all headers are kept.
+    pub finality_depth: Option<u32>,
+    // Seconds that the tip's timestamp can be behind the Secret Network block time before
+    // GetStatus reports the contract as stale, 3 hours if not given.
+    pub stale_threshold: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    // Required when migrating from version 0, whose state only kept the hash of the
+    // current block header.
+    pub from_v0: Option<MigrateFromV0>,
+}
+
+// The current block header's details, like InitMsg takes for the start block.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateFromV0 {
+    // Difficulty bits declared by the current block header.
+    pub curr_bits: u32,
+    // Timestamp of the current block header.
+    pub curr_time: u32,
+    // Timestamp of the first block header in the current block's difficulty epoch.
+    pub epoch_start_time: u32,
+    // Timestamps of the block headers preceding the current block, oldest first.
+    pub prev_timestamps: Vec<u32>,
+    // Number of most recent block headers to keep in the header history, 0 keeps all of them.
+    pub header_retention: u32,
+    // Total work of the chain up to and including the current block, as a big endian
+    // hex string.
+    pub curr_chainwork: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -20,8 +79,100 @@ pub enum HandleMsg {
     // 4) time: u32
     // 5) bits: u32
     // 6) nonce: u32
-    UpdateBlockOffset { block_headers: Vec<String> },
-    ResetState { new_state: InitMsg },
+    //
+    // With accept_prefix, the valid headers before the first invalid one are accepted
+    // as long as there are at least min_update_length of them, and the response log
+    // reports the index of the invalid header and why it was rejected.
+    //
+    // With skip_callbacks, the callbacks that the update makes due aren't executed and
+    // are left for TriggerCallbacks, so that a callback whose message fails can't keep
+    // the update from being accepted.
+    UpdateBlockOffset {
+        block_headers: Vec<String>,
+        accept_prefix: Option<bool>,
+        skip_callbacks: Option<bool>,
+    },
+    // The same as UpdateBlockOffset, but with the raw 80 byte block headers
+    // concatenated together, which is half the size of the hex encoding.
+    UpdateBlockOffsetPacked {
+        headers: Binary,
+        accept_prefix: Option<bool>,
+        skip_callbacks: Option<bool>,
+    },
+    ResetState {
+        new_state: InitMsg,
+    },
+    // Block headers of a competing branch that starts after the stored block
+    // header at fork_point_height, in the same format as UpdateBlockOffset.
+    // Replaces the current chain if the branch has more cumulative work.
+    SubmitFork {
+        fork_point_height: u32,
+        block_headers: Vec<String>,
+        skip_callbacks: Option<bool>,
+    },
+    // Offers ownership of the contract to new_owner, who has to accept it with
+    // AcceptOwnership. Only the owner can do this, and a later offer replaces an earlier one.
+    TransferOwnership {
+        new_owner: HumanAddr,
+    },
+    AcceptOwnership {},
+    // Grants an admin the given permissions, replacing the ones it had before.
+    // An empty list removes the admin. Only the owner can do this.
+    SetAdmin {
+        address: HumanAddr,
+        permissions: Vec<Permission>,
+    },
+    // Changes the validation settings. Values that are left out stay the same.
+    // Requires the configure permission.
+    UpdateConfig {
+        min_difficulty_bits: Option<u32>,
+        min_update_length: Option<u32>,
+        header_retention: Option<u32>,
+        max_update_length: Option<u32>,
+        finality_depth: Option<u32>,
+        stale_threshold: Option<u64>,
+    },
+    // Stops accepting block headers until Unpause, for when the contract is under attack
+    // or a bug is found. Requires the pause permission.
+    Pause {},
+    Unpause {},
+    // Adds the uscrt sent with the message to the pool that relayers are rewarded from.
+    Fund {},
+    // Sets the reward paid from the pool for every accepted block header, and the most
+    // paid for a single update, 0 for no limit. Only the owner can do this.
+    SetRewards {
+        reward_per_header: Uint128,
+        max_reward_per_update: Uint128,
+    },
+    // Executes msg on another contract once the condition is met. The update that meets
+    // it executes the callback, a limited number at a time, and TriggerCallbacks executes
+    // any that are left over. An address can only have a limited number of pending
+    // callbacks. The callback's id is returned in a RegisterCallbackResponse.
+    RegisterCallback {
+        condition: CallbackCondition,
+        contract_addr: HumanAddr,
+        callback_code_hash: String,
+        msg: Binary,
+    },
+    // Cancels a pending callback. Only the address that registered it or the owner can do this.
+    CancelCallback {
+        id: u64,
+    },
+    // Executes the due callback with the id, or without one the oldest due callbacks, a
+    // limited number at a time.
+    TriggerCallbacks {
+        id: Option<u64>,
+    },
+    // Pins the hash of the block header at a height, as a little endian hex string.
+    // Headers at the height with any other hash are rejected. Only the owner can add
+    // or remove checkpoints.
+    AddCheckpoint {
+        height: u32,
+        hash: String,
+    },
+    RemoveCheckpoint {
+        height: u32,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -29,6 +180,72 @@ pub enum HandleMsg {
 pub enum QueryMsg {
     // GetContractInfo returns the current offset, current hash, start height, and difficulty
     GetContractInfo {},
+    // GetCurrentTime returns the Bitcoin time according to the latest block headers.
+    // The time queries take an optional number of confirmations, counting the block
+    // itself like Bitcoin Core does, and only consider blocks that have at least that
+    // many. Blocks closer to the tip are more likely to be reorganized.
+    GetCurrentTime {
+        confirmations: Option<u32>,
+    },
+    // GetFinalizedTip returns the block header finality_depth blocks below the tip
+    GetFinalizedTip {},
+    // GetStatus reports whether the tip is behind the Secret Network block time by more
+    // than the stale threshold. Queries can't see the current block time, so it's
+    // compared with block_time if given, like the caller's env.block.time, and otherwise
+    // with the block time of the last update.
+    GetStatus {
+        block_time: Option<u64>,
+    },
+    // GetHeader returns the stored block header record at a height
+    GetHeader {
+        height: u32,
+    },
+    // GetHeaderByHash returns the stored block header record with a little endian hash
+    GetHeaderByHash {
+        hash: String,
+    },
+    // HeightAtTime returns the first stored block header with a timestamp of at least
+    // the given time, or with a median time past of at least it if use_mtp is set.
+    HeightAtTime {
+        timestamp: u32,
+        use_mtp: Option<bool>,
+        confirmations: Option<u32>,
+    },
+    // TimeAtHeight returns the timestamp of the stored block header at a height
+    TimeAtHeight {
+        height: u32,
+        confirmations: Option<u32>,
+    },
+    // VerifyTxInclusion checks a merkle proof that a transaction is included in the block
+    // at height. The txid and merkle branch hashes are little endian hex, and the branch is
+    // ordered from the transaction's sibling up to the merkle root. index is the position
+    // of the transaction in the block.
+    VerifyTxInclusion {
+        height: u32,
+        txid: String,
+        merkle_branch: Vec<String>,
+        index: u32,
+    },
+    // GetAdmins returns the owner, the pending owner and the admins with their permissions
+    GetAdmins {},
+    // GetRewardPool returns the reward pool balance and reward settings
+    GetRewardPool {},
+    // GetRelayerRewards returns the total rewards paid to a relayer
+    GetRelayerRewards {
+        relayer: HumanAddr,
+    },
+    // GetVersion returns the version of the stored state's layout
+    GetVersion {},
+    // GetCallbacks returns pending callbacks in the order they were registered
+    GetCallbacks {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // GetCheckpoints returns checkpoints in order of height
+    GetCheckpoints {
+        start_after: Option<u32>,
+        limit: Option<u32>,
+    },
 }
 
 // We define a custom struct for each query response
@@ -42,4 +259,179 @@ pub struct InfoResponse {
     pub curr_hash: String,
     pub curr_offset: u32,
     pub min_update_length: u32,
+    // 0 if there is no limit
+    pub max_update_length: u32,
+    pub finality_depth: u32,
+    // Total work of the chain up to and including the current block
+    // U256
+    pub chainwork: String,
+    // Whether block header updates are paused
+    pub paused: bool,
+    pub network: Network,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CurrentTimeResponse {
+    // Height of the latest accepted block header with the requested confirmations.
+    pub tip_height: u32,
+    // Timestamp of that block header.
+    pub tip_time: u32,
+    // Median of the latest block header timestamps, including the tip.
+    pub median_time_past: u32,
+    // A time that can safely be treated as having passed. This is the median time
+    // past minus the two hours that nodes allow block timestamps to be ahead of their
+    // clock, so it holds as long as one of the blocks at or above the median was
+    // accepted by honest nodes.
+    pub lower_bound_time: u32,
+    // While paused the time isn't being updated, so it shouldn't be relied on.
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HeaderResponse {
+    pub height: u32,
+    // Little endian hex
+    pub hash: String,
+    pub time: u32,
+    pub bits: u32,
+    // Little endian hex, not known for the start block.
+    pub merkle_root: Option<String>,
+    // Total work of the chain up to and including this block
+    // U256
+    pub chainwork: String,
+    // While paused the tip isn't being updated, so the block may come from a branch
+    // that was orphaned.
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatusResponse {
+    pub tip_height: u32,
+    pub tip_time: u32,
+    // Secret Network block height and time of the last update that accepted block
+    // headers, or of init or reset_state if there hasn't been one since.
+    pub last_update_block_height: u64,
+    pub last_update_block_time: u64,
+    // The Secret Network block time that the tip was compared with.
+    pub block_time: u64,
+    // Seconds that the tip's timestamp is behind block_time.
+    pub lag: u64,
+    pub stale_threshold: u64,
+    // Whether lag is greater than stale_threshold.
+    pub stale: bool,
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BlockTimeResponse {
+    pub height: u32,
+    // Little endian hex
+    pub hash: String,
+    pub time: u32,
+    // Median time past of the chain up to and including this block, not known for
+    // block headers accepted before time lookups were added.
+    pub median_time_past: Option<u32>,
+    // While paused the tip isn't being updated, so the block may come from a branch
+    // that was orphaned.
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TxInclusionResponse {
+    pub height: u32,
+    // Little endian hex
+    pub block_hash: String,
+    pub block_time: u32,
+    // Number of blocks from the tip down to and including the block
+    pub confirmations: u32,
+    // While paused the tip isn't being updated, so confirmations may be missing
+    // or come from a branch that was orphaned.
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AdminInfo {
+    pub address: HumanAddr,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AdminsResponse {
+    pub owner: HumanAddr,
+    // Set while an ownership transfer is waiting to be accepted
+    pub pending_owner: Option<HumanAddr>,
+    pub admins: Vec<AdminInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardPoolResponse {
+    pub denom: String,
+    pub pool: Uint128,
+    pub reward_per_header: Uint128,
+    // 0 if there is no limit
+    pub max_reward_per_update: Uint128,
+    pub total_paid: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RelayerRewardsResponse {
+    pub relayer: HumanAddr,
+    pub rewards_paid: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RegisterCallbackResponse {
+    pub id: u64,
+}
+
+// Returned in the data of the messages that change the tip: update_block_offset,
+// update_block_offset_packed, submit_fork and reset_state.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UpdateResult {
+    // Leading headers that were already accepted before this update.
+    pub headers_skipped: u32,
+    pub headers_accepted: u32,
+    pub tip_height: u32,
+    pub tip_hash: String,
+    pub tip_time: u32,
+    // The header that stopped an update with accept_prefix, if there was one.
+    pub rejected: Option<RejectedHeader>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RejectedHeader {
+    // Index in the list of headers that was passed in.
+    pub index: u32,
+    pub code: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CallbackInfo {
+    pub id: u64,
+    pub registrant: HumanAddr,
+    pub condition: CallbackCondition,
+    pub contract_addr: HumanAddr,
+    pub callback_code_hash: String,
+    pub msg: Binary,
+    // Whether the condition is already met and the callback is waiting to be executed
+    pub due: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CallbacksResponse {
+    pub callbacks: Vec<CallbackInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VersionResponse {
+    // Version of the layout of the stored state, bumped whenever it changes.
+    pub version: u32,
+    // Version of the contract code.
+    pub crate_version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CheckpointsResponse {
+    pub checkpoints: Vec<Checkpoint>,
 }
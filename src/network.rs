@@ -0,0 +1,67 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// The Bitcoin network that block headers are relayed from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Mainnet
+    }
+}
+
+// Consensus parameters that header verification depends on, see chainparams.cpp
+// in Bitcoin Core.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetworkParams {
+    // The easiest target allowed by the network, in compact form.
+    pub pow_limit_bits: u32,
+    // Number of blocks in a difficulty epoch.
+    pub retarget_interval: u32,
+    // Expected duration of a difficulty epoch in seconds.
+    pub target_timespan: u32,
+    // Expected time between blocks in seconds.
+    pub target_spacing: u32,
+    // Whether a block more than twice the target spacing after the previous one may
+    // use the pow limit, like on testnet.
+    pub allow_min_difficulty_blocks: bool,
+    // Whether the bits stay the same at epoch boundaries, like on regtest.
+    pub no_retargeting: bool,
+}
+
+impl Network {
+    pub fn params(self) -> NetworkParams {
+        let mainnet = NetworkParams {
+            pow_limit_bits: 0x1d00ffff,
+            retarget_interval: 2016,
+            target_timespan: 14 * 24 * 60 * 60,
+            target_spacing: 10 * 60,
+            allow_min_difficulty_blocks: false,
+            no_retargeting: false,
+        };
+        match self {
+            Network::Mainnet => mainnet,
+            Network::Testnet => NetworkParams {
+                allow_min_difficulty_blocks: true,
+                ..mainnet
+            },
+            Network::Signet => NetworkParams {
+                pow_limit_bits: 0x1e0377ae,
+                ..mainnet
+            },
+            Network::Regtest => NetworkParams {
+                pow_limit_bits: 0x207fffff,
+                allow_min_difficulty_blocks: true,
+                no_retargeting: true,
+                ..mainnet
+            },
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::cmp;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use cosmwasm_std::to_vec;
+use snafu::{ResultExt, Snafu};
+
+use crate::contract::BLOCK_HEADER_LEN;
+use crate::header::{BlockHash, BlockHeader};
+use crate::msg::HandleMsg;
+
+// Bitcoin Core doesn't return more than this many headers from a single REST request.
+pub const MAX_REST_HEADERS: u32 = 2000;
+
+#[derive(Snafu, Debug)]
+pub enum RelayerError {
+    #[snafu(display("Could not read {}: {}", path, source))]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Unsupported url {}, expected http://host:port", url))]
+    BadUrl { url: String },
+
+    #[snafu(display("Request to {} failed: {}", url, source))]
+    Request { url: String, source: std::io::Error },
+
+    #[snafu(display("Request to {} returned \"{}\"", url, status))]
+    HttpStatus { url: String, status: String },
+
+    #[snafu(display(
+        "Block header \"{}\" is not {} hex characters",
+        header,
+        BLOCK_HEADER_LEN
+    ))]
+    BadHeader { header: String },
+
+    #[snafu(display("Block hash \"{}\" is not 64 hex characters", hash))]
+    BadHash { hash: String },
+
+    #[snafu(display("Block {} was not found in the header source", hash))]
+    UnknownBlock { hash: String },
+
+    #[snafu(display("Block header {} does not follow the block header before it", index))]
+    NotConnected { index: usize },
+
+    #[snafu(display(
+        "Only {} new block headers are available, at least {} are needed",
+        available,
+        required
+    ))]
+    TooFewHeaders { available: usize, required: u32 },
+
+    #[snafu(display("Could not serialize message: {}", msg))]
+    Serialize { msg: String },
+}
+
+// Somewhere to read block headers from. Headers are 160 character hex strings
+// in the format that UpdateBlockOffset takes.
+pub trait HeaderSource {
+    // Returns up to count block headers in order, starting with the one after
+    // the block with the given little endian hash.
+    fn headers_after(&mut self, hash: &str, count: u32) -> Result<Vec<String>, RelayerError>;
+}
+
+// Reads block headers from the REST interface of a Bitcoin Core node,
+// which is enabled with the -rest option.
+pub struct RestSource {
+    // Base url of the node, like http://127.0.0.1:8332
+    pub url: String,
+}
+
+impl HeaderSource for RestSource {
+    fn headers_after(&mut self, hash: &str, count: u32) -> Result<Vec<String>, RelayerError> {
+        // The node returns the headers starting with the block itself,
+        // and takes the hash in big endian.
+        let block_hash = parse_hash(hash)?;
+        let count = cmp::min(count, MAX_REST_HEADERS - 1);
+        let url = format!(
+            "{}/rest/headers/{}/{}.hex",
+            self.url.trim_end_matches('/'),
+            count + 1,
+            block_hash.to_display_hex()
+        );
+        let body = http_get(&url)?;
+        let mut headers = split_headers(body.trim())?;
+        if headers.is_empty() || header_hash(&headers[0]) != block_hash {
+            return Err(RelayerError::UnknownBlock {
+                hash: hash.to_string(),
+            });
+        }
+        headers.remove(0);
+        Ok(headers)
+    }
+}
+
+// Reads block headers from a file with one header per line.
+// Empty lines and lines starting with # are skipped.
+pub struct FileSource {
+    pub headers: Vec<String>,
+}
+
+impl FileSource {
+    pub fn open(path: &str) -> Result<FileSource, RelayerError> {
+        let contents = fs::read_to_string(path).context(ReadFile { path })?;
+        let mut headers = vec![];
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            headers.push(check_header(line)?);
+        }
+        Ok(FileSource { headers })
+    }
+}
+
+impl HeaderSource for FileSource {
+    fn headers_after(&mut self, hash: &str, count: u32) -> Result<Vec<String>, RelayerError> {
+        // The file may start with the block itself or with the one after it.
+        let block_hash = parse_hash(hash)?;
+        let start = if self.headers.is_empty() || prev_hash(&self.headers[0]) == block_hash {
+            Some(0)
+        } else {
+            self.headers
+                .iter()
+                .position(|header| header_hash(header) == block_hash)
+                .map(|pos| pos + 1)
+        };
+        match start {
+            Some(start) => Ok(self
+                .headers
+                .iter()
+                .skip(start)
+                .take(count as usize)
+                .cloned()
+                .collect()),
+            None => Err(RelayerError::UnknownBlock {
+                hash: hash.to_string(),
+            }),
+        }
+    }
+}
+
+// Reads up to count headers after the block with the little endian hash from the
+// source, and builds UpdateBlockOffset messages for them as JSON strings. Every
+// message has at least min_update_length headers, and at most batch_size headers
+// unless the remainder would be too short to submit on its own.
+pub fn relay(
+    source: &mut dyn HeaderSource,
+    hash: &str,
+    count: u32,
+    min_update_length: u32,
+    batch_size: u32,
+) -> Result<Vec<String>, RelayerError> {
+    let headers = source.headers_after(hash, count)?;
+    check_connected(hash, &headers)?;
+    update_messages(&headers, min_update_length, batch_size)
+}
+
+// Checks that every header references the hash of the one before it,
+// so the contract doesn't reject the batch halfway through.
+pub fn check_connected(hash: &str, headers: &[String]) -> Result<(), RelayerError> {
+    let mut block_hash = parse_hash(hash)?;
+    for (index, header) in headers.iter().enumerate() {
+        let block_header = parse_header(header)?;
+        if block_header.prev_hash != block_hash {
+            return Err(RelayerError::NotConnected { index });
+        }
+        block_hash = block_header.hash();
+    }
+    Ok(())
+}
+
+// Splits headers into UpdateBlockOffset messages, serialized as JSON strings.
+pub fn update_messages(
+    headers: &[String],
+    min_update_length: u32,
+    batch_size: u32,
+) -> Result<Vec<String>, RelayerError> {
+    let min_update_length = cmp::max(min_update_length, 1);
+    if headers.len() < min_update_length as usize {
+        return Err(RelayerError::TooFewHeaders {
+            available: headers.len(),
+            required: min_update_length,
+        });
+    }
+
+    let batch_size = cmp::max(batch_size, min_update_length) as usize;
+    let mut batches: Vec<Vec<String>> = headers.chunks(batch_size).map(|c| c.to_vec()).collect();
+    // Too few headers are left over for the last batch, so they go in the one before it.
+    if batches.len() > 1 && batches[batches.len() - 1].len() < min_update_length as usize {
+        let last = batches.pop().unwrap();
+        batches.last_mut().unwrap().extend(last);
+    }
+
+    let mut messages = vec![];
+    for block_headers in batches {
+        let msg = HandleMsg::UpdateBlockOffset {
+            block_headers,
+            accept_prefix: None,
+            skip_callbacks: None,
+        };
+        let json = to_vec(&msg).map_err(|err| RelayerError::Serialize {
+            msg: err.to_string(),
+        })?;
+        messages.push(String::from_utf8(json).unwrap());
+    }
+    Ok(messages)
+}
+
+// Splits concatenated hex block headers.
+fn split_headers(hex: &str) -> Result<Vec<String>, RelayerError> {
+    if hex.len() % BLOCK_HEADER_LEN != 0 {
+        return Err(RelayerError::BadHeader {
+            header: hex.to_string(),
+        });
+    }
+    let mut headers = vec![];
+    for i in (0..hex.len()).step_by(BLOCK_HEADER_LEN) {
+        headers.push(check_header(&hex[i..i + BLOCK_HEADER_LEN])?);
+    }
+    Ok(headers)
+}
+
+fn check_header(header: &str) -> Result<String, RelayerError> {
+    parse_header(header)?;
+    Ok(header.to_lowercase())
+}
+
+fn parse_header(header: &str) -> Result<BlockHeader, RelayerError> {
+    BlockHeader::from_hex(header).map_err(|_| RelayerError::BadHeader {
+        header: header.to_string(),
+    })
+}
+
+fn parse_hash(hash: &str) -> Result<BlockHash, RelayerError> {
+    BlockHash::from_internal_hex(hash).map_err(|_| RelayerError::BadHash {
+        hash: hash.to_string(),
+    })
+}
+
+// Headers from sources have already been checked, so these can't fail.
+fn header_hash(header: &str) -> BlockHash {
+    parse_header(header).unwrap().hash()
+}
+
+fn prev_hash(header: &str) -> BlockHash {
+    parse_header(header).unwrap().prev_hash
+}
+
+// Makes a plain HTTP GET request and returns the body of a successful response.
+fn http_get(url: &str) -> Result<String, RelayerError> {
+    let scheme = "http://";
+    if !url.starts_with(scheme) {
+        return Err(RelayerError::BadUrl {
+            url: url.to_string(),
+        });
+    }
+    let rest = &url[scheme.len()..];
+    let (host, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+
+    // HTTP/1.0 so that the server closes the connection after the response.
+    let mut stream = TcpStream::connect(host).context(Request { url })?;
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, host);
+    stream
+        .write_all(request.as_bytes())
+        .context(Request { url })?;
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .context(Request { url })?;
+
+    let (head, body) = match response.find("\r\n\r\n") {
+        Some(pos) => (&response[..pos], &response[pos + 4..]),
+        None => (&response[..], ""),
+    };
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(RelayerError::HttpStatus {
+            url: url.to_string(),
+            status: status.to_string(),
+        });
+    }
+    Ok(body.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    // The block before the headers below, 125551.
+    const START_HASH: &str = "81cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000";
+
+    // Block headers 125552 to 125555.
+    fn fixture_headers() -> Vec<String> {
+        vec![
+            "0100000081cd02ab7e569e8bcd9317e2fe99f2de44d49ab2b8851ba4a308000000000000e320b6c2fffc8d750423db8b1eb942ae710e951ed797f7affc8892b0f1fc122bc7f5d74df2b9441a42a14695".to_string(),
+            "010000001dbd981fe6985776b644b173a4d0385ddc1aa2a829688d1e0000000000000000b371c14921b20c2895ed76545c116e0ad70167c5c4952ca201f5d544a26efb53b4f6d74df2b9441a071a0c81".to_string(),
+            "0100000085afcb448a3fcde31dc78babd352d9dbde6fcb566777ea33051c000000000000ca5b6b96fe65e1a7d50e7c3025a176472ba26d44512de86a6f3e39649330cd2f16f7d74df2b9441a8574adaf".to_string(),
+            "010000001e60224709df1feb2e2849b7b10570abf7d4355ba8e2f6df121100000000000028cc65b7be2f8a1edc2af86ef369472443a1b70479cee205e8db5440cfbe943f57fad74df2b9441acc24ce5b".to_string(),
+        ]
+    }
+
+    // Serves the given number of requests, responding to paths that are in routes
+    // with their body and to anything else with a 404. Returns the server's url.
+    fn mock_server(routes: Vec<(String, String)>, requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = vec![];
+                let mut buf = [0u8; 1024];
+                while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap().to_string();
+                let response = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, body)) => format!("HTTP/1.0 200 OK\r\n\r\n{}", body),
+                    None => "HTTP/1.0 404 Not Found\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn rest_source() {
+        let headers = fixture_headers();
+        let first_hash = header_hash(&headers[0]);
+        // Bitcoin Core returns the requested block's header first.
+        let route = format!("/rest/headers/4/{}.hex", first_hash);
+        let url = mock_server(vec![(route, headers.concat() + "\n")], 2);
+
+        let mut source = RestSource { url: url.clone() };
+        let messages = relay(&mut source, &first_hash.to_internal_hex(), 3, 3, 3).unwrap();
+        assert_eq!(
+            vec![format!(
+                "{{\"update_block_offset\":{{\"block_headers\":[\"{}\",\"{}\",\"{}\"],\"accept_prefix\":null,\"skip_callbacks\":null}}}}",
+                headers[1], headers[2], headers[3]
+            )],
+            messages
+        );
+
+        // the node doesn't know the block
+        let res = source.headers_after(START_HASH, 3);
+        match res {
+            Err(RelayerError::HttpStatus { status, .. }) => {
+                assert_eq!("HTTP/1.0 404 Not Found", status)
+            }
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn file_source() {
+        let headers = fixture_headers();
+        let path =
+            std::env::temp_dir().join(format!("timekeeper-relayer-{}.txt", std::process::id()));
+        let contents = format!(
+            "# block headers 125552 to 125555\n\n{}\n",
+            headers.join("\n")
+        );
+        fs::write(&path, contents).unwrap();
+        let mut source = FileSource::open(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // the file starts right after the start block
+        assert_eq!(headers, source.headers_after(START_HASH, 10).unwrap());
+        // or the headers can start after any block in the file
+        let second_hash = header_hash(&headers[1]).to_internal_hex();
+        assert_eq!(
+            headers[2..3].to_vec(),
+            source.headers_after(&second_hash, 1).unwrap()
+        );
+        match source.headers_after(&"00".repeat(32), 1) {
+            Err(RelayerError::UnknownBlock { .. }) => {}
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn batches() {
+        let headers = fixture_headers();
+
+        // the leftover header joins the last batch
+        let messages = update_messages(&headers, 3, 3).unwrap();
+        assert_eq!(1, messages.len());
+        let messages = update_messages(&headers, 1, 3).unwrap();
+        assert_eq!(2, messages.len());
+        let messages = update_messages(&headers, 2, 2).unwrap();
+        assert_eq!(2, messages.len());
+
+        match update_messages(&headers, 5, 5) {
+            Err(RelayerError::TooFewHeaders {
+                available,
+                required,
+            }) => {
+                assert_eq!(4, available);
+                assert_eq!(5, required);
+            }
+            _ => panic!("Must return an error"),
+        }
+    }
+
+    #[test]
+    fn not_connected() {
+        let mut headers = fixture_headers();
+        assert!(check_connected(START_HASH, &headers).is_ok());
+        headers.swap(1, 2);
+        match check_connected(START_HASH, &headers) {
+            Err(RelayerError::NotConnected { index }) => assert_eq!(1, index),
+            _ => panic!("Must return an error"),
+        }
+    }
+}
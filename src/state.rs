@@ -1,10 +1,20 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Storage};
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use cosmwasm_std::{CanonicalAddr, Storage, Uint128};
+
+use crate::network::Network;
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
 
 pub static CONFIG_KEY: &[u8] = b"config";
+pub static TIMESTAMPS_KEY: &[u8] = b"timestamps";
+pub static ADMINS_KEY: &[u8] = b"admins";
+pub static REWARDS_KEY: &[u8] = b"rewards";
+pub static RELAYER_REWARDS_KEY: &[u8] = b"relayer_rewards";
+pub static VERSION_KEY: &[u8] = b"version";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
@@ -21,6 +31,57 @@ pub struct State {
     // Intended to increase the amount of work for creating invalid chains.
     pub min_update_length: u32,
     pub owner: CanonicalAddr,
+    // Address that the owner has offered to transfer ownership to.
+    // It becomes the owner once it accepts.
+    pub pending_owner: Option<CanonicalAddr>,
+    // Compact encoding of the target that the current block header declares.
+    // Headers in the same difficulty epoch must declare the same bits.
+    pub curr_bits: u32,
+    // The timestamp of the current block header.
+    pub curr_time: u32,
+    // The timestamp of the first block header in the current difficulty epoch,
+    // used to compute the retarget at the next epoch boundary.
+    pub epoch_start_time: u32,
+    // Number of most recent block headers kept in the header history.
+    // If 0, every accepted block header is kept.
+    pub header_retention: u32,
+    // Total work of the chain up to and including the current block.
+    // Big endian hex representation of a U256, since that type isn't serializable.
+    pub chainwork: String,
+    // While paused, new block headers aren't accepted.
+    pub paused: bool,
+    // The network that block headers are relayed from. Added in version 2, so
+    // older layouts load as mainnet.
+    #[serde(default)]
+    pub network: Network,
+    // Difficulty bits of the first block header in the current difficulty epoch.
+    // On networks that allow minimum difficulty blocks, the block after one goes
+    // back to these bits. Added in version 2 and filled in by migrate.
+    #[serde(default)]
+    pub epoch_bits: u32,
+    // When updating, can pass in at most this many blocks, so that updates fit in the
+    // block gas limit. If 0, there is no limit. Added in version 4, so older layouts
+    // load without a limit.
+    #[serde(default)]
+    pub max_update_length: u32,
+    // Number of blocks below the tip that the finalized tip is, for consumers that
+    // can't risk relying on blocks that might still be reorganized. Added in version 6.
+    #[serde(default)]
+    pub finality_depth: u32,
+    // Secret Network block height and time of the last update that accepted block
+    // headers. Added in version 7, so they are 0 until the first update after migrating.
+    #[serde(default)]
+    pub last_update_block_height: u64,
+    #[serde(default)]
+    pub last_update_block_time: u64,
+    // How many seconds the tip's timestamp can be behind the Secret Network block time
+    // before GetStatus reports the contract as stale. Added in version 7.
+    #[serde(default)]
+    pub stale_threshold: u64,
+    // Height of the oldest stored block header record. Every height from it up to the
+    // tip is stored. Added in version 8 and filled in by migrate.
+    #[serde(default)]
+    pub oldest_height: u32,
 }
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
@@ -30,3 +91,108 @@ pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
 pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+// The State layout of contracts instantiated before versioning was added, which are
+// version 0. Only used to migrate them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StateV0 {
+    pub start_height: u32,
+    pub curr_offset: u32,
+    pub curr_hash: String,
+    pub threshold_difficulty: String,
+    pub min_update_length: u32,
+    pub owner: CanonicalAddr,
+}
+
+pub fn config_v0_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, StateV0> {
+    singleton_read(storage, CONFIG_KEY)
+}
+
+// Version of the layout of the stored state. Missing for contracts instantiated
+// before versioning was added.
+pub fn version<S: Storage>(storage: &mut S) -> Singleton<S, u32> {
+    singleton(storage, VERSION_KEY)
+}
+
+pub fn version_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, u32> {
+    singleton_read(storage, VERSION_KEY)
+}
+
+// Timestamps of the most recent block headers, oldest first.
+// Used to compute the median time past of the current block.
+pub fn timestamps<S: Storage>(storage: &mut S) -> Singleton<S, Vec<u32>> {
+    singleton(storage, TIMESTAMPS_KEY)
+}
+
+pub fn timestamps_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<u32>> {
+    singleton_read(storage, TIMESTAMPS_KEY)
+}
+
+// Actions that the owner can delegate to admins. The owner can do all of them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Reset the contract state with ResetState.
+    Reset,
+    // Change the validation settings with UpdateConfig.
+    Configure,
+    // Pause and unpause updates.
+    Pause,
+}
+
+impl Permission {
+    // The name the permission is serialized with.
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Reset => "reset",
+            Permission::Configure => "configure",
+            Permission::Pause => "pause",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Admin {
+    pub address: CanonicalAddr,
+    pub permissions: Vec<Permission>,
+}
+
+// Admins that the owner has granted permissions to.
+pub fn admins<S: Storage>(storage: &mut S) -> Singleton<S, Vec<Admin>> {
+    singleton(storage, ADMINS_KEY)
+}
+
+pub fn admins_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<Admin>> {
+    singleton_read(storage, ADMINS_KEY)
+}
+
+// The pool that relayers are paid from for submitting block headers.
+// Amounts are in REWARD_DENOM.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Rewards {
+    // Paid for every accepted block header.
+    pub per_header: Uint128,
+    // The most paid for a single update, 0 for no limit.
+    pub max_per_update: Uint128,
+    // Funds left to pay rewards with.
+    pub pool: Uint128,
+    // Total paid to all relayers.
+    pub total_paid: Uint128,
+}
+
+pub fn rewards<S: Storage>(storage: &mut S) -> Singleton<S, Rewards> {
+    singleton(storage, REWARDS_KEY)
+}
+
+pub fn rewards_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Rewards> {
+    singleton_read(storage, REWARDS_KEY)
+}
+
+// Total rewards paid to each relayer, keyed by canonical address.
+pub fn relayer_rewards<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(RELAYER_REWARDS_KEY, storage)
+}
+
+pub fn relayer_rewards_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(RELAYER_REWARDS_KEY, storage)
+}